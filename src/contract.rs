@@ -1,24 +1,59 @@
 use cosmwasm_std::{
-    coins, log, to_binary, Api, BankMsg, Binary, Context, CosmosMsg, Empty, Env, Extern,
-    HandleResponse, HumanAddr, InitResponse, Querier, StdResult, Storage, WasmMsg,
+    coin, coins, from_binary, log, to_binary, Api, BankMsg, Binary, Coin, Context, CosmosMsg,
+    Empty, Env, Extern, HandleResponse, HumanAddr, InitResponse, Order, Querier, ReadonlyStorage,
+    StdError, StdResult, Storage, Uint128, WasmMsg,
 };
+use std::convert::TryFrom;
 
 use crate::hand::{rand_hand, Hand, MatchResult};
-use crate::msg::{HandleMsg, InitMsg, QueryMsg};
+use crate::migration::{
+    load_v0_1_config, load_v2_config, migrate_v0_1, migrate_v2, migrate_v3, stored_version, v2, v3,
+};
+use crate::msg::{
+    BetLimitsResponse, ConfigResponse, ContractStatusResponse, FeeLedgerResponse, HandleMsg,
+    InitMsg, OfferRole, OffersResponse, OpenOfferTerms, PublicOfferResponse, PublishedSide,
+    QueryMsg, QueryWithPermit, ReceiveNftMsg, ReceiveTokenMsg, SharesResponse, TokenBetsResponse,
+};
 use crate::msg_cw721::HandleMsg as Cw721HandleMsg;
+use crate::msg_snip20::HandleMsg as Snip20HandleMsg;
+use crate::permit::{Permission, Permit};
 use crate::state::{
-    config, config_read, offers, offers_read, read_viewing_key, token_bets, token_bets_read,
-    write_viewing_key, Offer, OfferStatus, State, TokenBet,
+    accrued_fees, accrued_fees_read, bet_limits, bet_limits_read, bettor_bets, bettor_bets_read,
+    config, config_read, contract_address, contract_version, current_epoch_read, epoch_seed,
+    epochs_read, held_reserves, held_reserves_read, next_offer_id, next_token_bet_id,
+    nft_contracts, offer_keys, offeree_offers, offeree_offers_read, offeror_offers,
+    offeror_offers_read, offers, offers_read, pending_bets, remove_viewing_key, reserves,
+    reserves_read, revoked_permits, rotate_epoch, shares, shares_read, token_bet_keys, token_bets,
+    token_bets_read, token_contracts, token_contracts_read, total_shares, total_shares_read,
+    write_viewing_key, BetLimits, ContractStatus, Expiration, Nft, NftCollection, Offer,
+    OfferStatus, PendingBet, State, TokenBet,
 };
-use crate::utils::{calculate_fee, sha_256, Prng};
+use crate::utils::{calculate_fee, house_seed, range_start, sha_256, Prng};
 use crate::validation::{
-    validate_balance, validate_nft, validate_offer_id, validate_offeree, validate_sent_funds,
-    validate_token_bet_id, validate_withdrawer,
+    validate_admin, validate_bet_amount, validate_bet_limits, validate_contract_status,
+    validate_draw_point, validate_expiration, validate_fee_rate, validate_hands,
+    validate_lp_fee_share, validate_max_hands, validate_max_nfts, validate_nft_contract,
+    validate_nfts, validate_offer_key, validate_offeree, validate_offeree_funds,
+    validate_offeree_hands, validate_offeree_nfts, validate_offeree_terms, validate_offeror,
+    validate_pending_bet, validate_publisher, validate_query_auth, validate_reserve,
+    validate_sent_funds, validate_shares, validate_stakes, validate_token_bet_key,
+    validate_token_contract, validate_withdrawer, QueryAuth,
 };
 use crate::viewing_key::ViewingKey;
 
+/// the version of the stored records, bumped when any stored type changes
+pub const CONTRACT_VERSION: u32 = 4;
 pub const INVERSE_BASIS_POINT: u64 = 10000;
 pub const DEFAULT_FEE_RATE: u64 = 300;
+pub const DEFAULT_LP_FEE_SHARE: u64 = 5000;
+pub const DEFAULT_MAX_PAYOUT_RATE: u64 = 1000;
+pub const DEFAULT_MAX_HANDS: u32 = 10;
+pub const DEFAULT_MAX_NFTS: u32 = 10;
+/// the upper bound of max_hands, so that the draw point of any offer fits in i8
+pub const MAX_HANDS_LIMIT: u32 = 128;
+pub const MAX_NFTS_LIMIT: u32 = 30;
+pub const DEFAULT_LIMIT: u32 = 10;
+pub const MAX_LIMIT: u32 = 30;
 
 pub fn init<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
@@ -27,17 +62,37 @@ pub fn init<S: Storage, A: Api, Q: Querier>(
 ) -> StdResult<InitResponse> {
     let state = State {
         prng_seed: sha_256(base64::encode(msg.prng_seed.clone()).as_bytes()).to_vec(),
-        entropy: msg.prng_seed.as_bytes().to_vec(),
+        // started apart from the prng seed, so the holder of it can't follow the entropy
+        entropy: sha_256(
+            &[
+                &env.block.height.to_be_bytes()[..],
+                &env.block.time.to_be_bytes(),
+                env.contract.address.0.as_bytes(),
+                env.message.sender.0.as_bytes(),
+            ]
+            .concat(),
+        )
+        .to_vec(),
+        admin: env.message.sender.clone(),
+        pending_admin: None,
         fee_recipient: match msg.fee_recipient {
             Some(recipient) => recipient,
             None => env.message.sender,
         },
         fee_rate: match msg.fee_rate {
-            Some(rate) => rate,
+            Some(rate) => validate_fee_rate(rate)?,
             None => DEFAULT_FEE_RATE,
         },
+        lp_fee_share: DEFAULT_LP_FEE_SHARE,
+        max_hands: DEFAULT_MAX_HANDS,
+        max_nfts: DEFAULT_MAX_NFTS,
+        contract_status: ContractStatus::Normal,
     };
     config(&mut deps.storage).save(&state)?;
+    contract_version(&mut deps.storage).save(&CONTRACT_VERSION)?;
+    contract_address(&mut deps.storage).save(&env.contract.address)?;
+    // the house seed derived from the prng seed is committed as the seed of the first epoch
+    rotate_epoch(&mut deps.storage, &house_seed(&state.prng_seed))?;
 
     Ok(InitResponse::default())
 }
@@ -47,148 +102,322 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
     env: Env,
     msg: HandleMsg,
 ) -> StdResult<HandleResponse> {
+    // the config of the older version can't be loaded until migrated
+    if !matches!(msg, HandleMsg::Migrate {}) {
+        let state: State = config_read(&deps.storage).load()?;
+        validate_contract_status(state.contract_status, &msg)?;
+    }
+
     match msg {
         HandleMsg::MakeOffer {
             id,
             offeree,
-            offeror_nft_contract,
-            offeror_nft,
-            offeror_code_hash,
-            offeree_nft_contract,
-            offeree_nft,
-            offeree_code_hash,
+            offeror_nfts,
+            offeree_nfts,
+            offeree_collection,
+            offeree_funds,
             offeror_hands,
             offeror_draw_point,
+            expires,
+            escrow,
         } => try_offer(
             deps,
             env,
             id,
             offeree,
-            offeror_nft_contract,
-            offeror_nft,
-            offeror_code_hash,
-            offeree_nft_contract,
-            offeree_nft,
-            offeree_code_hash,
+            offeror_nfts,
+            offeree_nfts,
+            offeree_collection,
+            offeree_funds,
             offeror_hands,
             offeror_draw_point,
+            expires,
+            escrow,
         ),
-        HandleMsg::AcceptOffer { id, offeree_hands } => try_accept(deps, env, id, offeree_hands),
+        HandleMsg::AcceptOffer {
+            id,
+            offeree_hands,
+            offeree_nft,
+        } => try_accept(deps, env, id, offeree_hands, offeree_nft),
         HandleMsg::DeclineOffer { id } => try_decline(deps, env, id),
-        HandleMsg::BetToken { id, hand, entropy } => try_bet_token(deps, env, id, hand, entropy),
+        HandleMsg::CounterOffer {
+            id,
+            offeror_nfts,
+            offeree_nfts,
+            offeree_funds,
+            offeror_hands,
+            offeror_draw_point,
+            expires,
+            escrow,
+        } => try_counter_offer(
+            deps,
+            env,
+            id,
+            offeror_nfts,
+            offeree_nfts,
+            offeree_funds,
+            offeror_hands,
+            offeror_draw_point,
+            expires,
+            escrow,
+        ),
+        HandleMsg::CancelOffer { id } => try_cancel(deps, env, id),
+        HandleMsg::PublishOffer { id } => try_publish_offer(deps, env, id),
+        HandleMsg::RegisterReceiveNft {
+            nft_contract,
+            code_hash,
+        } => try_register_receive_nft(deps, env, nft_contract, code_hash),
+        HandleMsg::ReceiveNft {
+            sender,
+            token_id,
+            msg,
+        } => try_receive_nft(deps, env, sender, vec![token_id], msg),
+        HandleMsg::BatchReceiveNft {
+            from,
+            token_ids,
+            msg,
+            ..
+        } => try_receive_nft(deps, env, from, token_ids, msg),
+        HandleMsg::BetToken {
+            id,
+            hand,
+            entropy,
+            commit,
+        } => try_bet_token(deps, env, id, hand, entropy, commit),
+        HandleMsg::SettleBet { id } => try_settle_bet(deps, env, id),
+        HandleMsg::RegisterToken {
+            token_contract,
+            code_hash,
+        } => try_register_token(deps, env, token_contract, code_hash),
+        HandleMsg::Receive {
+            from, amount, msg, ..
+        } => try_receive(deps, env, from, amount, msg),
         HandleMsg::WithdrawFee { denom, amount } => try_withdraw_fee(deps, env, denom, amount),
+        HandleMsg::Deposit {} => try_deposit(deps, env),
+        HandleMsg::Withdraw { denom, shares } => try_withdraw(deps, env, denom, shares),
+        HandleMsg::RotateSeed { seed } => try_rotate_seed(deps, env, seed),
+        HandleMsg::SetBetLimits {
+            denom,
+            min_bet,
+            max_bet,
+            max_payout_rate,
+        } => try_set_bet_limits(
+            deps,
+            env,
+            denom,
+            BetLimits {
+                min_bet,
+                max_bet,
+                max_payout_rate,
+            },
+        ),
         HandleMsg::GenerateViewingKey { entropy, .. } => {
             try_generate_viewing_key(deps, env, entropy)
         }
+        HandleMsg::SetViewingKey { key, .. } => try_set_viewing_key(deps, env, key),
+        HandleMsg::RevokeViewingKey { .. } => try_revoke_viewing_key(deps, env),
+        HandleMsg::RevokePermit { permit_name, .. } => try_revoke_permit(deps, env, permit_name),
+        HandleMsg::UpdateConfig {
+            fee_rate,
+            fee_recipient,
+            lp_fee_share,
+            max_hands,
+            max_nfts,
+        } => try_update_config(
+            deps,
+            env,
+            fee_rate,
+            fee_recipient,
+            lp_fee_share,
+            max_hands,
+            max_nfts,
+        ),
+        HandleMsg::ChangeAdmin { address } => try_change_admin(deps, env, address),
+        HandleMsg::AcceptAdmin {} => try_accept_admin(deps, env),
+        HandleMsg::SetContractStatus { level } => try_set_contract_status(deps, env, level),
+        HandleMsg::Migrate {} => try_migrate(deps, env),
     }
 }
 
 pub fn try_offer<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
-    id: u64,
-    offeree: HumanAddr,
-    offeror_nft_contract: HumanAddr,
-    offeror_nft: String,
-    offeror_code_hash: String,
-    offeree_nft_contract: HumanAddr,
-    offeree_nft: String,
-    offeree_code_hash: String,
+    id: Option<u64>,
+    offeree: Option<HumanAddr>,
+    offeror_nfts: Vec<Nft>,
+    offeree_nfts: Option<Vec<Nft>>,
+    offeree_collection: Option<NftCollection>,
+    offeree_funds: Option<Vec<Coin>>,
     hands: Vec<u8>,
     draw_point: i8,
+    expires: Option<Expiration>,
+    escrow: Option<bool>,
 ) -> StdResult<HandleResponse> {
-    validate_offer_id(&deps, id)?;
-    validate_nft(
-        &deps,
-        offeror_nft_contract.clone(),
-        offeror_nft.clone(),
-        offeror_code_hash.clone(),
-        env.message.sender.clone(),
-    )?;
-    validate_nft(
-        &deps,
-        offeree_nft_contract.clone(),
-        offeree_nft.clone(),
-        offeree_code_hash.clone(),
-        offeree.clone(),
-    )?;
-
+    let state: State = config_read(&deps.storage).load()?;
+    let hands = validate_hands(&hands, state.max_hands)?;
+    let (offeree, offeree_nfts, offeree_collection) =
+        validate_offeree_terms(offeree, offeree_nfts, offeree_collection)?;
     let offer = Offer::new(
-        id,
         env.message.sender.clone(),
         offeree,
-        offeror_nft_contract,
-        offeror_nft,
-        offeror_code_hash,
-        offeree_nft_contract,
-        offeree_nft,
-        offeree_code_hash,
+        offeror_nfts,
+        offeree_nfts,
+        offeree_collection,
+        env.message.sent_funds.clone(),
+        offeree_funds.unwrap_or_default(),
         hands,
         draw_point,
+        expires.unwrap_or_default(),
+        escrow.unwrap_or(false),
     );
 
-    offers(&mut deps.storage).save(&id.to_be_bytes(), &offer)?;
+    let (id, messages) = save_offer(deps, env, id, offer, false, &[])?;
 
     Ok(HandleResponse {
-        messages: vec![],
+        messages,
         log: vec![log("action", "offered")],
-        data: None,
+        data: Some(to_binary(&id)?),
     })
 }
 
+// returns the assigned offer id and the messages to escrow the offeror nfts.
+// `key` is the optional idempotency key given by client.
+// `deposited` is true when the offeror nfts have already been sent to the contract.
+// `held` are the offeree nfts which the contract holds until refunded in the same transaction
+fn save_offer<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    key: Option<u64>,
+    mut offer: Offer,
+    deposited: bool,
+    held: &[Nft],
+) -> StdResult<(u64, Vec<CosmosMsg>)> {
+    let offeror = deps.api.canonical_address(&offer.offeror)?;
+    if let Some(key) = key {
+        validate_offer_key(&deps, &offeror, key)?;
+    }
+    validate_expiration(&offer.expires, &env.block)?;
+    validate_draw_point(offer.offeror_draw_point, &offer.offeror_hands)?;
+    let state: State = config_read(&deps.storage).load()?;
+    validate_stakes(&offer.offeror_nfts, &offer.offeror_funds, state.max_nfts)?;
+    if !deposited {
+        validate_nfts(&deps, &offer.offeror_nfts, &offer.offeror)?;
+    }
+    // the offeree nft of the open offer is validated when accepted
+    if !offer.open {
+        validate_stakes(&offer.offeree_nfts, &offer.offeree_funds, state.max_nfts)?;
+        let unheld: Vec<Nft> = offer
+            .offeree_nfts
+            .iter()
+            .filter(|nft| !held.contains(nft))
+            .cloned()
+            .collect();
+        validate_nfts(&deps, &unheld, &offer.offeree)?;
+        if offer.offeror_nfts.is_empty() && offer.offeree_nfts.is_empty() {
+            return Err(StdError::generic_err("either side should stake nfts"));
+        }
+    }
+
+    // the offeror nfts are kept in the contract until the offer is closed
+    let mut messages = vec![];
+    if offer.escrow && !deposited {
+        for nft in &offer.offeror_nfts {
+            messages.push(transfer_nft_msg(nft, env.contract.address.clone())?);
+        }
+    }
+
+    offer.id = next_offer_id(&mut deps.storage)?;
+    if let Some(key) = key {
+        offer_keys(&mut deps.storage, &offeror).save(&key.to_be_bytes(), &offer.id)?;
+    }
+    offers(&mut deps.storage).save(&offer.id.to_be_bytes(), &offer)?;
+    offeror_offers(&mut deps.storage, &offeror).save(&offer.id.to_be_bytes(), &offer.id)?;
+    if !offer.open {
+        let offeree = deps.api.canonical_address(&offer.offeree)?;
+        offeree_offers(&mut deps.storage, &offeree).save(&offer.id.to_be_bytes(), &offer.id)?;
+    }
+
+    Ok((offer.id, messages))
+}
+
 pub fn try_accept<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
     id: u64,
     hands: Vec<u8>,
+    offeree_nft: Option<String>,
 ) -> StdResult<HandleResponse> {
-    let mut offer = validate_offeree(deps, env.message.sender.clone(), id)?;
+    let offeree = env.message.sender.clone();
+    accept_offer(deps, env, offeree, id, hands, offeree_nft, false)
+}
+
+// `deposited` is true when the offeree nfts have already been sent to the contract
+fn accept_offer<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    offeree: HumanAddr,
+    id: u64,
+    hands: Vec<u8>,
+    offeree_nft: Option<String>,
+    deposited: bool,
+) -> StdResult<HandleResponse> {
+    let mut offer = validate_offeree(deps, offeree.clone(), id)?;
+    if offer.expires.is_expired(&env.block) {
+        // fail to give the deposited nfts or the sent funds back to the sender
+        if deposited || !env.message.sent_funds.is_empty() {
+            return Err(StdError::generic_err(format!(
+                "offer({}) has expired",
+                offer.id
+            )));
+        }
+        return expire_offer(deps, &env.contract.address, offer);
+    }
 
-    offer.accept_offer(env.message.sender.clone(), hands);
+    let hands = validate_offeree_hands(&hands, &offer.offeror_hands)?;
+    validate_offeree_funds(&env.message.sent_funds, &offer.offeree_funds)?;
+    // the first holder to accept becomes the offeree of the open offer
+    if offer.open {
+        let offeree_nfts = validate_offeree_nfts(&offer, offeree_nft)?;
+        if !deposited {
+            validate_nfts(&deps, &offeree_nfts, &offeree)?;
+        }
+        offer.offeree_nfts = offeree_nfts;
+        let canonical_offeree = deps.api.canonical_address(&offeree)?;
+        offeree_offers(&mut deps.storage, &canonical_offeree)
+            .save(&offer.id.to_be_bytes(), &offer.id)?;
+    } else {
+        validate_offeree_nfts(&offer, offeree_nft)?;
+    }
+    offer.accept_offer(offeree, hands);
     let offeror_hands = &offer.offeror_hands;
     let offeree_hands = &offer.offeree_hands;
 
     let mut ctx = Context::new();
     ctx.add_log("action", "accepted");
 
-    let result = offeror_hands.compete(offeree_hands, offer.offeror_draw_point);
+    let result = offeror_hands.compete(offeree_hands, offer.offeror_draw_point)?;
 
-    if result.eq(&MatchResult::Draw) {
-        offer.winner = "draw".to_string();
-    } else {
-        offer.winner = if result.eq(&MatchResult::Win) {
-            "offeror".to_string()
-        } else {
-            "offeree".to_string()
-        };
-        let msg = to_binary(&Cw721HandleMsg::TransferNft {
-            recipient: if result.eq(&MatchResult::Win) {
-                offer.offeror.clone()
-            } else {
-                offer.offeree.clone()
-            },
-            token_id: if result.eq(&MatchResult::Win) {
-                offer.offeree_nft.clone()
-            } else {
-                offer.offeror_nft.clone()
-            },
-            memo: None,
-            padding: None,
-        })?;
-        ctx.add_message(WasmMsg::Execute {
-            contract_addr: if result.eq(&MatchResult::Win) {
-                offer.offeree_nft_contract.clone()
-            } else {
-                offer.offeror_nft_contract.clone()
-            },
-            callback_code_hash: if result.eq(&MatchResult::Win) {
-                offer.offeree_code_hash.clone()
-            } else {
-                offer.offeror_code_hash.clone()
-            },
-            msg,
-            send: vec![],
-        });
+    offer.winner = match result {
+        MatchResult::Win => "offeror".to_string(),
+        MatchResult::Lose => "offeree".to_string(),
+        MatchResult::Draw => "draw".to_string(),
+    };
+
+    if offer.escrow && !deposited {
+        for nft in &offer.offeree_nfts {
+            ctx.add_message(transfer_nft_msg(nft, env.contract.address.clone())?);
+        }
+    }
+    for msg in settle_nfts(&offer, &result, offer.escrow || deposited)? {
+        ctx.add_message(msg);
+    }
+    let state: State = config_read(&deps.storage).load()?;
+    let (messages, fees) = settle_funds(&offer, &result, &env.contract.address, state.fee_rate);
+    for msg in messages {
+        ctx.add_message(msg);
+    }
+    for fee in fees {
+        accrue_fee(&mut deps.storage, &fee.denom, fee.amount.u128())?;
     }
 
     offers(&mut deps.storage).update(&id.to_be_bytes(), |_| Ok(offer))?;
@@ -202,474 +431,3900 @@ pub fn try_decline<S: Storage, A: Api, Q: Querier>(
     id: u64,
 ) -> StdResult<HandleResponse> {
     let mut offer = validate_offeree(deps, env.message.sender.clone(), id)?;
+    if offer.open {
+        return Err(StdError::generic_err(format!(
+            "open offer({}) can't be declined",
+            offer.id
+        )));
+    }
+    if offer.expires.is_expired(&env.block) {
+        return expire_offer(deps, &env.contract.address, offer);
+    }
 
     offer.decline_offer(env.message.sender.clone());
+    let messages = refund_offeror(&offer, &env.contract.address)?;
     offers(&mut deps.storage).update(&id.to_be_bytes(), |_| Ok(offer))?;
 
     Ok(HandleResponse {
-        messages: vec![],
+        messages,
         log: vec![log("action", "declined")],
         data: None,
     })
 }
 
-pub fn try_bet_token<S: Storage, A: Api, Q: Querier>(
+// the offeree makes the linked offer in which the roles are swapped, then the original offer
+// is closed. the hands are committed again by both players
+pub fn try_counter_offer<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
     id: u64,
-    hand: u8,
-    entropy: String,
+    offeror_nfts: Option<Vec<Nft>>,
+    offeree_nfts: Option<Vec<Nft>>,
+    offeree_funds: Option<Vec<Coin>>,
+    hands: Vec<u8>,
+    draw_point: i8,
+    expires: Option<Expiration>,
+    escrow: Option<bool>,
 ) -> StdResult<HandleResponse> {
-    validate_token_bet_id(&deps, id)?;
-    // check sent funds
-    let fund = validate_sent_funds(env.message.sent_funds)?;
-    // check contract balance
-    validate_balance(
-        &deps,
-        &env.contract.address,
-        &fund.denom,
-        fund.amount.u128(),
-    )?;
-
-    // generate and save new random bytes
-    let mut state: State = config_read(&deps.storage).load()?;
-    let rng = Prng::new_rand_bytes(&state.entropy, (&entropy).as_ref());
-    state.entropy = rng.clone();
-    config(&mut deps.storage).save(&state)?;
-
-    // compete
-    let opponent_hand = rand_hand(&rng);
-    let result = Hand::from(&hand).compete(&opponent_hand);
-
-    let denom = &fund.denom;
-    let amount = fund.amount.u128() as u64;
-    let fee = calculate_fee(amount, state.fee_rate);
-    let messages: Vec<CosmosMsg<Empty>> = match &result {
-        MatchResult::Win => {
-            vec![CosmosMsg::Bank(BankMsg::Send {
-                from_address: env.contract.address.clone(),
-                to_address: env.message.sender.clone(),
-                amount: coins((amount * 2 - fee).into(), denom),
-            })]
-        }
-        MatchResult::Draw => {
-            vec![CosmosMsg::Bank(BankMsg::Send {
-                from_address: env.contract.address.clone(),
-                to_address: env.message.sender.clone(),
-                amount: coins((amount - fee).into(), denom),
-            })]
+    let mut offer = validate_offeree(deps, env.message.sender.clone(), id)?;
+    if offer.open {
+        return Err(StdError::generic_err(format!(
+            "open offer({}) can't be countered",
+            offer.id
+        )));
+    }
+    if offer.expires.is_expired(&env.block) {
+        // fail to give the sent funds back to the sender
+        if !env.message.sent_funds.is_empty() {
+            return Err(StdError::generic_err(format!(
+                "offer({}) has expired",
+                offer.id
+            )));
         }
-        MatchResult::Lose => vec![],
-    };
+        return expire_offer(deps, &env.contract.address, offer);
+    }
 
-    let token_bet = TokenBet {
-        id,
-        denom: denom.to_string(),
-        amount,
-        hand: Hand::from(&hand),
-        result: result.to_string(),
+    let state: State = config_read(&deps.storage).load()?;
+    let hands = validate_hands(&hands, state.max_hands)?;
+    let mut counter_offer = Offer::new(
+        env.message.sender.clone(),
+        offer.offeror.clone(),
+        offeror_nfts.unwrap_or_else(|| offer.offeree_nfts.clone()),
+        offeree_nfts.unwrap_or_else(|| offer.offeror_nfts.clone()),
+        None,
+        env.message.sent_funds.clone(),
+        offeree_funds.unwrap_or_else(|| offer.offeror_funds.clone()),
+        hands,
+        draw_point,
+        expires.unwrap_or_default(),
+        escrow.unwrap_or(false),
+    );
+    counter_offer.counter_of = Some(offer.id);
+    // the escrowed nfts are owned by the contract until refunded below
+    let held = if offer.escrow {
+        offer.offeror_nfts.clone()
+    } else {
+        vec![]
     };
+    let contract_addr = env.contract.address.clone();
+    let (counter_id, mut messages) = save_offer(deps, env, None, counter_offer, false, &held)?;
 
-    token_bets(&mut deps.storage).save(&id.to_be_bytes(), &token_bet)?;
+    offer.counter_offer(counter_id);
+    messages.append(&mut refund_offeror(&offer, &contract_addr)?);
+    offers(&mut deps.storage).update(&id.to_be_bytes(), |_| Ok(offer))?;
 
     Ok(HandleResponse {
         messages,
-        log: vec![log("action", "bet"), log("result", result.to_str())],
-        data: None,
+        log: vec![log("action", "countered")],
+        data: Some(to_binary(&counter_id)?),
     })
 }
 
-pub fn try_withdraw_fee<S: Storage, A: Api, Q: Querier>(
+pub fn try_cancel<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
-    denom: String,
-    amount: u64,
+    id: u64,
 ) -> StdResult<HandleResponse> {
-    validate_withdrawer(&deps, &env.message.sender, &denom, amount)?;
+    let mut offer = validate_offeror(deps, env.message.sender.clone(), id)?;
+
+    offer.cancel_offer();
+    let messages = refund_offeror(&offer, &env.contract.address)?;
+    offers(&mut deps.storage).update(&id.to_be_bytes(), |_| Ok(offer))?;
 
     Ok(HandleResponse {
-        messages: vec![CosmosMsg::Bank(BankMsg::Send {
-            from_address: env.contract.address,
-            to_address: env.message.sender,
-            amount: coins(amount.into(), &denom),
-        })],
-        log: vec![log("action", "withdrawn"), log("amout", amount)],
+        messages,
+        log: vec![log("action", "cancelled")],
         data: None,
     })
 }
 
-pub fn try_generate_viewing_key<S: Storage, A: Api, Q: Querier>(
+pub fn try_publish_offer<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
-    entropy: String,
+    id: u64,
 ) -> StdResult<HandleResponse> {
-    let config: State = config_read(&deps.storage).load()?;
-    let key = ViewingKey::new(&env, &config.prng_seed, (&entropy).as_ref());
+    let mut offer = validate_publisher(&deps, &env.message.sender, id)?;
 
-    let message_sender = deps.api.canonical_address(&env.message.sender)?;
-    write_viewing_key(&mut deps.storage, &message_sender, &key);
+    offer.publish(&env.message.sender);
+    offers(&mut deps.storage).save(&id.to_be_bytes(), &offer)?;
 
     Ok(HandleResponse {
         messages: vec![],
-        log: vec![log("action", "generated")],
-        data: Some(to_binary(&key)?),
+        log: vec![log("action", "published")],
+        data: None,
     })
 }
 
-pub fn query<S: Storage, A: Api, Q: Querier>(
-    deps: &Extern<S, A, Q>,
-    msg: QueryMsg,
-) -> StdResult<Binary> {
-    match msg {
-        QueryMsg::Offer {
-            id,
-            address,
-            viewing_key,
-        } => query_offer(&deps, id, address, viewing_key),
-        // QueryMsg::Offers {} => query_offers(&deps),
-        QueryMsg::TokenBet { id } => query_token_bets(&deps, id),
+// the offer is closed as expired instead of being answered, so that it never gets matched
+fn expire_offer<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    contract_addr: &HumanAddr,
+    mut offer: Offer,
+) -> StdResult<HandleResponse> {
+    let id = offer.id;
+    offer.expire_offer();
+    let messages = refund_offeror(&offer, contract_addr)?;
+    offers(&mut deps.storage).update(&id.to_be_bytes(), |_| Ok(offer))?;
+
+    Ok(HandleResponse {
+        messages,
+        log: vec![log("action", "expired")],
+        data: None,
+    })
+}
+
+// the winner obtains all nfts, each nft goes back to its owner on draw.
+// the nft which is not held by the contract is transferred only when it changes owner
+fn settle_nfts(
+    offer: &Offer,
+    result: &MatchResult,
+    offeree_nfts_held: bool,
+) -> StdResult<Vec<CosmosMsg>> {
+    let (offeror_nfts_recipient, offeree_nfts_recipient) = stake_recipients(offer, result);
+
+    let mut messages = vec![];
+    if offer.escrow || offeror_nfts_recipient != offer.offeror {
+        for nft in &offer.offeror_nfts {
+            messages.push(transfer_nft_msg(nft, offeror_nfts_recipient.clone())?);
+        }
     }
+    if offeree_nfts_held || offeree_nfts_recipient != offer.offeree {
+        for nft in &offer.offeree_nfts {
+            messages.push(transfer_nft_msg(nft, offeree_nfts_recipient.clone())?);
+        }
+    }
+    Ok(messages)
 }
 
-fn query_offer<S: Storage, A: Api, Q: Querier>(
-    deps: &Extern<S, A, Q>,
-    id: u64,
-    address: Option<HumanAddr>,
-    viewing_key: Option<String>,
-) -> StdResult<Binary> {
-    let can_view_hands = match viewing_key {
-        Some(viewing_key) => {
-            let key = ViewingKey(viewing_key);
-            let message_sender = deps.api.canonical_address(&address.unwrap())?;
-            let expected_key = read_viewing_key(&deps.storage, &message_sender);
-            key.check_viewing_key(expected_key.unwrap().as_slice())
-        }
-        None => false,
+// the winner obtains all funds, each fund goes back to its sender on draw.
+// the fee is charged on the funds staked instead of nfts when they are paid to the nft owner,
+// and returned along with the messages to be accrued
+fn settle_funds(
+    offer: &Offer,
+    result: &MatchResult,
+    contract_addr: &HumanAddr,
+    fee_rate: u64,
+) -> (Vec<CosmosMsg>, Vec<Coin>) {
+    let (offeror_funds_recipient, offeree_funds_recipient) = stake_recipients(offer, result);
+    let mut fees = vec![];
+    let offeror_funds = if offer.offeror_nfts.is_empty() && offeror_funds_recipient != offer.offeror
+    {
+        deduct_fee(&offer.offeror_funds, fee_rate, &mut fees)
+    } else {
+        offer.offeror_funds.clone()
     };
-    match offers_read(&deps.storage).may_load(&id.to_be_bytes()) {
-        Ok(Some(mut o)) => {
-            let hide_hands = o.status == OfferStatus::Offered && !can_view_hands;
-            if hide_hands {
-                o.offeror_hands = Vec::<Hand>::new().into();
-            }
-            return to_binary(&o);
-        }
-        _ => return to_binary(""),
+    let offeree_funds = if offer.offeree_nfts.is_empty() && offeree_funds_recipient != offer.offeree
+    {
+        deduct_fee(&offer.offeree_funds, fee_rate, &mut fees)
+    } else {
+        offer.offeree_funds.clone()
     };
+
+    let mut messages = send_funds_msgs(contract_addr, offeror_funds_recipient, &offeror_funds);
+    messages.append(&mut send_funds_msgs(
+        contract_addr,
+        offeree_funds_recipient,
+        &offeree_funds,
+    ));
+    (messages, fees)
 }
 
-// fn query_offers<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<Binary> {
-//     let res_data: StdResult<Vec<KV<Offer>>> = offers_read(&deps.storage)
-//         .range(None, None, Order::Ascending)
-//         .collect();
-//     let data = res_data.unwrap();
-//     let ids: Vec<u64> = data
-//         .iter()
-//         .map(|(k, _)| u64::from_be_bytes(to_array::<u8, 8>(k.to_vec())))
-//         .collect();
-//     to_binary(&OffersResponse { ids })
-// }
+fn deduct_fee(funds: &[Coin], fee_rate: u64, fees: &mut Vec<Coin>) -> Vec<Coin> {
+    funds
+        .iter()
+        .map(|fund| {
+            let amount = fund.amount.u128();
+            let fee = calculate_fee(amount, fee_rate);
+            fees.push(coin(fee, &fund.denom));
+            coin(amount - fee, &fund.denom)
+        })
+        .collect()
+}
 
-fn query_token_bets<S: Storage, A: Api, Q: Querier>(
-    deps: &Extern<S, A, Q>,
-    id: u64,
-) -> StdResult<Binary> {
-    match token_bets_read(&deps.storage).may_load(&id.to_be_bytes()) {
-        Ok(Some(bet)) => to_binary(&bet),
-        _ => return to_binary(""),
-    }
+fn accrue_fee<S: Storage>(storage: &mut S, denom: &str, fee: u128) -> StdResult<Uint128> {
+    accrued_fees(storage).update(denom.as_bytes(), |accrued_fee| {
+        Ok(Uint128(accrued_fee.unwrap_or_default().u128() + fee))
+    })
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::hand::{Hand, Hands};
-    use crate::mock::{mock_dependencies, MockQuerier};
-    use crate::utils::calculate_fee;
-    use cosmwasm_std::testing::{mock_env, MockApi, MockStorage};
-    use cosmwasm_std::{from_binary, Coin, StdError};
-    use std::collections::HashMap;
+// returns the recipients of the offeror stakes and the offeree stakes
+fn stake_recipients(offer: &Offer, result: &MatchResult) -> (HumanAddr, HumanAddr) {
+    match result {
+        MatchResult::Win => (offer.offeror.clone(), offer.offeror.clone()),
+        MatchResult::Lose => (offer.offeree.clone(), offer.offeree.clone()),
+        MatchResult::Draw => (offer.offeror.clone(), offer.offeree.clone()),
+    }
+}
 
-    fn initialize() -> Extern<MockStorage, MockApi, MockQuerier> {
-        let denom = "uscrt".to_string();
-        let init_amount = coins(10000, &denom);
-        let env = mock_env("deployer", &init_amount);
-        let contract_addr = env.contract.address.clone();
+// the nfts in escrow and the sent funds go back to the offeror
+fn refund_offeror(offer: &Offer, contract_addr: &HumanAddr) -> StdResult<Vec<CosmosMsg>> {
+    let mut messages = vec![];
+    if offer.escrow {
+        for nft in &offer.offeror_nfts {
+            messages.push(transfer_nft_msg(nft, offer.offeror.clone())?);
+        }
+    }
+    messages.append(&mut send_funds_msgs(
+        contract_addr,
+        offer.offeror.clone(),
+        &offer.offeror_funds,
+    ));
+    Ok(messages)
+}
 
-        let owners = HashMap::from([
-            ("nft_id_1".to_string(), HumanAddr("nft_owner_1".to_string())),
-            ("nft_id_2".to_string(), HumanAddr("nft_owner_2".to_string())),
-            ("nft_id_3".to_string(), HumanAddr("nft_owner_3".to_string())),
-        ]);
-        let balance: &[(&HumanAddr, &[Coin])] = &[
-            (&HumanAddr::from("deployer"), &init_amount),
-            (&contract_addr, &init_amount),
-            (&HumanAddr::from("bettor_1"), &init_amount),
-            (&HumanAddr::from("bettor_2"), &init_amount),
-        ];
-        let mut deps = mock_dependencies(balance, Some(owners));
-        let msg = InitMsg {
-            prng_seed: "prng_seed".to_string(),
-            fee_recipient: None,
-            fee_rate: None,
-        };
-        init(&mut deps, env, msg).unwrap();
-        deps
+fn send_funds_msgs(
+    contract_addr: &HumanAddr,
+    recipient: HumanAddr,
+    funds: &[Coin],
+) -> Vec<CosmosMsg> {
+    if funds.is_empty() {
+        return vec![];
     }
+    vec![CosmosMsg::Bank(BankMsg::Send {
+        from_address: contract_addr.clone(),
+        to_address: recipient,
+        amount: funds.to_vec(),
+    })]
+}
 
-    fn valid_sample_offer_msg(id: u64) -> HandleMsg {
-        HandleMsg::MakeOffer {
-            id,
-            offeree: "nft_owner_2".into(),
-            offeror_nft_contract: "offeror_contract".into(),
-            offeror_nft: "nft_id_1".to_string(),
-            offeror_code_hash: "offeror_code_hash".to_string(),
-            offeree_nft_contract: "offeree_contract".into(),
-            offeree_nft: "nft_id_2".to_string(),
-            offeree_code_hash: "offeree_code_hash".to_string(),
-            offeror_hands: vec![1, 2, 3],
-            offeror_draw_point: -1,
-        }
+fn transfer_nft_msg(nft: &Nft, recipient: HumanAddr) -> StdResult<CosmosMsg> {
+    let msg = to_binary(&Cw721HandleMsg::TransferNft {
+        recipient,
+        token_id: nft.token_id.clone(),
+        memo: None,
+        padding: None,
+    })?;
+    Ok(WasmMsg::Execute {
+        contract_addr: nft.contract.clone(),
+        callback_code_hash: nft.code_hash.clone(),
+        msg,
+        send: vec![],
     }
+    .into())
+}
 
-    #[test]
-    fn proper_initialization() {
-        let mut deps = mock_dependencies(&[(&HumanAddr::from(""), &[])], None);
-        let msg = InitMsg {
-            prng_seed: "prng_seed".to_string(),
-            fee_recipient: None,
-            fee_rate: None,
-        };
-        init(&mut deps, mock_env("creator", &[]), msg).unwrap();
+// sends the coins of the denom, or the tokens if the denom is a whitelisted snip20 contract
+fn send_msg<S: ReadonlyStorage>(
+    storage: &S,
+    contract_addr: &HumanAddr,
+    recipient: HumanAddr,
+    denom: &str,
+    amount: u128,
+) -> StdResult<CosmosMsg> {
+    match token_contracts_read(storage).may_load(denom.as_bytes())? {
+        Some(code_hash) => {
+            let msg = to_binary(&Snip20HandleMsg::Transfer {
+                recipient,
+                amount: Uint128(amount),
+                padding: None,
+            })?;
+            Ok(WasmMsg::Execute {
+                contract_addr: denom.into(),
+                callback_code_hash: code_hash,
+                msg,
+                send: vec![],
+            }
+            .into())
+        }
+        None => Ok(BankMsg::Send {
+            from_address: contract_addr.clone(),
+            to_address: recipient,
+            amount: coins(amount, denom),
+        }
+        .into()),
     }
+}
 
-    #[test]
-    fn try_offer() {
-        let mut deps = initialize();
+pub fn try_register_receive_nft<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    nft_contract: HumanAddr,
+    code_hash: String,
+) -> StdResult<HandleResponse> {
+    // the sender of the registered contract is trusted as the nft owner
+    validate_admin(&deps, &env.message.sender)?;
+    let contract = deps.api.canonical_address(&nft_contract)?;
+    nft_contracts(&mut deps.storage).save(contract.as_slice(), &code_hash)?;
 
-        let offer_id = 100;
-        let env = mock_env("nft_owner_1", &[]);
-        let msg = valid_sample_offer_msg(offer_id);
+    let msg = to_binary(&Cw721HandleMsg::RegisterReceiveNft {
+        code_hash: env.contract_code_hash,
+        also_implements_batch_receive_nft: Some(true),
+        padding: None,
+    })?;
+
+    Ok(HandleResponse {
+        messages: vec![CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: nft_contract,
+            callback_code_hash: code_hash,
+            msg,
+            send: vec![],
+        })],
+        log: vec![log("action", "registered")],
+        data: None,
+    })
+}
+
+pub fn try_receive_nft<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    from: HumanAddr,
+    token_ids: Vec<String>,
+    msg: Option<Binary>,
+) -> StdResult<HandleResponse> {
+    let nft_contract = env.message.sender.clone();
+    let code_hash = validate_nft_contract(&deps, &nft_contract)?;
+    let msg = match msg {
+        Some(msg) => msg,
+        None => return Err(StdError::generic_err("missing receive msg")),
+    };
+
+    match from_binary::<ReceiveNftMsg>(&msg)? {
+        ReceiveNftMsg::MakeOffer {
+            id,
+            offeree,
+            offeree_nfts,
+            offeree_collection,
+            offeree_funds,
+            offeror_hands,
+            offeror_draw_point,
+            expires,
+        } => {
+            let state: State = config_read(&deps.storage).load()?;
+            let offeror_hands = validate_hands(&offeror_hands, state.max_hands)?;
+            let (offeree, offeree_nfts, offeree_collection) =
+                validate_offeree_terms(offeree, offeree_nfts, offeree_collection)?;
+            let offeror_nfts = token_ids
+                .into_iter()
+                .map(|token_id| Nft {
+                    contract: nft_contract.clone(),
+                    code_hash: code_hash.clone(),
+                    token_id,
+                })
+                .collect();
+            let offer = Offer::new(
+                from,
+                offeree,
+                offeror_nfts,
+                offeree_nfts,
+                offeree_collection,
+                vec![],
+                offeree_funds.unwrap_or_default(),
+                offeror_hands,
+                offeror_draw_point,
+                expires.unwrap_or_default(),
+                true,
+            );
+            let (id, messages) = save_offer(deps, env, id, offer, true, &[])?;
+
+            Ok(HandleResponse {
+                messages,
+                log: vec![log("action", "offered")],
+                data: Some(to_binary(&id)?),
+            })
+        }
+        ReceiveNftMsg::AcceptOffer { id, offeree_hands } => {
+            let offer = offers_read(&deps.storage).load(&id.to_be_bytes())?;
+            let offeree_nft = match &offer.offeree_collection {
+                // only one token is staked for the open offer
+                Some(collection) if offer.open => {
+                    if collection.contract != nft_contract || token_ids.len() != 1 {
+                        return Err(StdError::generic_err(format!(
+                            "sent nfts are not one token from the offered contract({})",
+                            collection.contract
+                        )));
+                    }
+                    Some(token_ids[0].clone())
+                }
+                // all the offeree nfts are sent at once
+                _ => {
+                    if token_ids.len() != offer.offeree_nfts.len()
+                        || !offer.offeree_nfts.iter().all(|nft| {
+                            nft.contract == nft_contract && token_ids.contains(&nft.token_id)
+                        })
+                    {
+                        return Err(StdError::generic_err(format!(
+                            "sent nfts({:?}) are not the offered ones",
+                            token_ids
+                        )));
+                    }
+                    None
+                }
+            };
+            accept_offer(deps, env, from, id, offeree_hands, offeree_nft, true)
+        }
+    }
+}
+
+pub fn try_bet_token<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    key: Option<u64>,
+    hand: u8,
+    entropy: String,
+    commit: Option<bool>,
+) -> StdResult<HandleResponse> {
+    // check sent funds
+    let fund = validate_sent_funds(env.message.sent_funds.clone())?;
+    let bettor = env.message.sender.clone();
+    let commit = commit.unwrap_or(false);
+    bet_token(deps, env, bettor, fund, key, hand, entropy, commit)
+}
+
+// the fund is the sent coins or the received snip20 tokens
+fn bet_token<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    bettor: HumanAddr,
+    fund: Coin,
+    key: Option<u64>,
+    hand: u8,
+    entropy: String,
+    commit: bool,
+) -> StdResult<HandleResponse> {
+    let hand = Hand::try_from(&hand)?;
+    let bettor_raw = deps.api.canonical_address(&bettor)?;
+    if let Some(key) = key {
+        validate_token_bet_key(&deps, &bettor_raw, key)?;
+    }
+    // check house reserve and bet limits
+    let reserve = validate_reserve(&deps, &fund.denom, fund.amount.u128())?;
+    let mut state: State = config_read(&deps.storage).load()?;
+    validate_bet_amount(
+        &deps,
+        &fund.denom,
+        fund.amount.u128(),
+        reserve,
+        state.fee_rate,
+    )?;
+
+    let id = next_token_bet_id(&mut deps.storage)?;
+    if let Some(key) = key {
+        token_bet_keys(&mut deps.storage, &bettor_raw).save(&key.to_be_bytes(), &id)?;
+    }
+    let denom = fund.denom;
+    let amount = fund.amount;
+
+    // generate and save new random bytes
+    state.entropy = Prng::new_rand_bytes(&state.entropy, (&entropy).as_ref());
+    config(&mut deps.storage).save(&state)?;
+
+    // the bet is given its own entropy derived from the state, since revealing the state itself
+    // would let the seed holder predict the next bets
+    let bet_entropy = Binary(Prng::new_rand_bytes(&state.entropy, &id.to_be_bytes()));
+    let epoch = current_epoch_read(&deps.storage).load()?;
+    if commit {
+        // the reserve is held for the bet, whose result is fixed now but drawn in a later block,
+        // so it can't be known by simulating the bet nor changed by anyone settling it
+        reserves(&mut deps.storage).save(denom.as_bytes(), &Uint128(reserve - amount.u128()))?;
+        let held = held_reserve(&deps.storage, &denom)?;
+        held_reserves(&mut deps.storage).save(denom.as_bytes(), &Uint128(held + amount.u128()))?;
+        let pending_bet = PendingBet {
+            id,
+            bettor: bettor_raw.clone(),
+            denom: denom.clone(),
+            amount,
+            hand,
+            height: env.block.height,
+            epoch,
+            entropy: bet_entropy,
+        };
+        pending_bets(&mut deps.storage).save(&id.to_be_bytes(), &pending_bet)?;
+        // listed in the history of the bettor until it's settled
+        let token_bet = TokenBet {
+            id,
+            bettor: bettor_raw,
+            denom,
+            amount,
+            hand,
+            result: "pending".to_string(),
+            epoch: 0,
+            entropy: Binary(vec![]),
+        };
+        save_token_bet(&mut deps.storage, &token_bet)?;
+
+        return Ok(HandleResponse {
+            messages: vec![],
+            log: vec![log("action", "bet"), log("result", "pending")],
+            data: Some(to_binary(&id)?),
+        });
+    }
+
+    let token_bet = TokenBet {
+        id,
+        bettor: bettor_raw,
+        denom,
+        amount,
+        hand,
+        result: String::new(),
+        epoch,
+        entropy: bet_entropy,
+    };
+    let (messages, result) = settle_token_bet(
+        &mut deps.storage,
+        &env.contract.address,
+        &state,
+        bettor,
+        token_bet,
+        reserve,
+    )?;
+
+    Ok(HandleResponse {
+        messages,
+        log: vec![log("action", "bet"), log("result", result.to_str())],
+        data: Some(to_binary(&id)?),
+    })
+}
+
+// competes against the hand drawn from the epoch and the entropy of the bet, and pays out from
+// the reserve
+fn settle_token_bet<S: Storage>(
+    storage: &mut S,
+    contract_addr: &HumanAddr,
+    state: &State,
+    bettor: HumanAddr,
+    mut token_bet: TokenBet,
+    reserve: u128,
+) -> StdResult<(Vec<CosmosMsg>, MatchResult)> {
+    // the house hand can be recomputed with the seed revealed after the epoch
+    let seed = epoch_seed(storage, token_bet.epoch)?;
+    let rng = Prng::new_rand_bytes(&seed, token_bet.entropy.as_slice());
+
+    // compete
+    let opponent_hand = rand_hand(&rng);
+    let result = token_bet.hand.compete(&opponent_hand);
+
+    let denom = &token_bet.denom;
+    let amount = token_bet.amount.u128();
+    let fee = calculate_fee(amount, state.fee_rate);
+    let payout = match &result {
+        MatchResult::Win => amount * 2 - fee,
+        MatchResult::Draw => amount - fee,
+        MatchResult::Lose => 0,
+    };
+    let messages: Vec<CosmosMsg<Empty>> = if payout > 0 {
+        vec![send_msg(storage, contract_addr, bettor, denom, payout)?]
+    } else {
+        vec![]
+    };
+
+    // the fee is set aside from the bet except the cut for the liquidity providers,
+    // the rest of the bet goes to the reserve
+    let lp_fee = calculate_fee(fee, state.lp_fee_share);
+    accrue_fee(storage, denom, fee - lp_fee)?;
+    let reserve = reserve + amount + lp_fee - payout - fee;
+    reserves(storage).save(denom.as_bytes(), &Uint128(reserve))?;
+
+    token_bet.result = result.to_string();
+    save_token_bet(storage, &token_bet)?;
+    Ok((messages, result))
+}
+
+pub fn try_settle_bet<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    id: u64,
+) -> StdResult<HandleResponse> {
+    let bet = validate_pending_bet(&deps, id, &env.block)?;
+    pending_bets(&mut deps.storage).remove(&id.to_be_bytes());
+    let held = held_reserve(&deps.storage, &bet.denom)?;
+    held_reserves(&mut deps.storage)
+        .save(bet.denom.as_bytes(), &Uint128(held - bet.amount.u128()))?;
+    let bettor = deps.api.human_address(&bet.bettor)?;
+    let reserve = reserves_read(&deps.storage)
+        .may_load(bet.denom.as_bytes())?
+        .unwrap_or_default()
+        .u128()
+        + bet.amount.u128();
+
+    // no game is played while the contract is stopped, so the bet is refunded
+    let state: State = config_read(&deps.storage).load()?;
+    if state.contract_status != ContractStatus::Normal {
+        reserves(&mut deps.storage).save(bet.denom.as_bytes(), &Uint128(reserve))?;
+        let token_bet = TokenBet {
+            id,
+            bettor: bet.bettor,
+            denom: bet.denom.clone(),
+            amount: bet.amount,
+            hand: bet.hand,
+            result: "refunded".to_string(),
+            epoch: 0,
+            entropy: Binary(vec![]),
+        };
+        save_token_bet(&mut deps.storage, &token_bet)?;
+
+        return Ok(HandleResponse {
+            messages: vec![send_msg(
+                &deps.storage,
+                &env.contract.address,
+                bettor,
+                &bet.denom,
+                bet.amount.u128(),
+            )?],
+            log: vec![log("action", "settled"), log("result", "refunded")],
+            data: None,
+        });
+    }
+
+    // the result was fixed at the bet, so it's the same whoever settles it and whenever
+    let token_bet = TokenBet {
+        id,
+        bettor: bet.bettor,
+        denom: bet.denom,
+        amount: bet.amount,
+        hand: bet.hand,
+        result: String::new(),
+        epoch: bet.epoch,
+        entropy: bet.entropy,
+    };
+    let (messages, result) = settle_token_bet(
+        &mut deps.storage,
+        &env.contract.address,
+        &state,
+        bettor,
+        token_bet,
+        reserve,
+    )?;
+
+    Ok(HandleResponse {
+        messages,
+        log: vec![log("action", "settled"), log("result", result.to_str())],
+        data: None,
+    })
+}
+
+// the bet is listed in the history of the bettor
+fn save_token_bet<S: Storage>(storage: &mut S, token_bet: &TokenBet) -> StdResult<()> {
+    let key = token_bet.id.to_be_bytes();
+    token_bets(storage).save(&key, token_bet)?;
+    bettor_bets(storage, &token_bet.bettor).save(&key, &token_bet.id)
+}
+
+pub fn try_register_token<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    token_contract: HumanAddr,
+    code_hash: String,
+) -> StdResult<HandleResponse> {
+    validate_admin(&deps, &env.message.sender)?;
+    token_contracts(&mut deps.storage).save(token_contract.as_str().as_bytes(), &code_hash)?;
+
+    let msg = to_binary(&Snip20HandleMsg::RegisterReceive {
+        code_hash: env.contract_code_hash,
+        padding: None,
+    })?;
+
+    Ok(HandleResponse {
+        messages: vec![CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: token_contract,
+            callback_code_hash: code_hash,
+            msg,
+            send: vec![],
+        })],
+        log: vec![log("action", "registered")],
+        data: None,
+    })
+}
+
+pub fn try_receive<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    from: HumanAddr,
+    amount: Uint128,
+    msg: Option<Binary>,
+) -> StdResult<HandleResponse> {
+    let token_contract = env.message.sender.clone();
+    validate_token_contract(&deps, &token_contract)?;
+    let msg = match msg {
+        Some(msg) => msg,
+        None => return Err(StdError::generic_err("missing receive msg")),
+    };
+    // the denom of the tokens is the contract address
+    let fund = Coin {
+        denom: token_contract.to_string(),
+        amount,
+    };
+
+    match from_binary::<ReceiveTokenMsg>(&msg)? {
+        ReceiveTokenMsg::BetToken {
+            id,
+            hand,
+            entropy,
+            commit,
+        } => {
+            let commit = commit.unwrap_or(false);
+            bet_token(deps, env, from, fund, id, hand, entropy, commit)
+        }
+        ReceiveTokenMsg::Deposit {} => deposit(deps, from, fund),
+    }
+}
+
+pub fn try_withdraw_fee<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    denom: String,
+    amount: Uint128,
+) -> StdResult<HandleResponse> {
+    let amount = amount.u128();
+    let accrued_fee = validate_withdrawer(&deps, &env.message.sender, &denom, amount)?;
+    accrued_fees(&mut deps.storage).save(denom.as_bytes(), &Uint128(accrued_fee - amount))?;
+
+    Ok(HandleResponse {
+        messages: vec![send_msg(
+            &deps.storage,
+            &env.contract.address,
+            env.message.sender,
+            &denom,
+            amount,
+        )?],
+        log: vec![log("action", "withdrawn"), log("amout", amount)],
+        data: None,
+    })
+}
+
+pub fn try_deposit<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    let fund = validate_sent_funds(env.message.sent_funds)?;
+    deposit(deps, env.message.sender, fund)
+}
+
+// the fund is the sent coins or the received snip20 tokens
+fn deposit<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    depositor: HumanAddr,
+    fund: Coin,
+) -> StdResult<HandleResponse> {
+    let denom = &fund.denom;
+    let amount = fund.amount.u128();
+    let reserve = reserves_read(&deps.storage)
+        .may_load(denom.as_bytes())?
+        .unwrap_or_default()
+        .u128();
+    let total = total_shares_read(&deps.storage)
+        .may_load(denom.as_bytes())?
+        .unwrap_or_default()
+        .u128();
+    // the part held for the pending bets is priced in until they are settled
+    let pooled = reserve + held_reserve(&deps.storage, denom)?;
+    let minted = shares_to_mint(amount, pooled, total);
+    if minted == 0 {
+        return Err(StdError::generic_err(format!(
+            "deposit({}{}) is too small to mint a share",
+            amount, denom
+        )));
+    }
+
+    let depositor = deps.api.canonical_address(&depositor)?;
+    shares(&mut deps.storage, denom).update(depositor.as_slice(), |owned| {
+        Ok(Uint128(owned.unwrap_or_default().u128() + minted))
+    })?;
+    total_shares(&mut deps.storage).save(denom.as_bytes(), &Uint128(total + minted))?;
+    reserves(&mut deps.storage).save(denom.as_bytes(), &Uint128(reserve + amount))?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "deposited"), log("shares", minted)],
+        data: None,
+    })
+}
+
+pub fn try_withdraw<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    denom: String,
+    amount: Uint128,
+) -> StdResult<HandleResponse> {
+    let owner = deps.api.canonical_address(&env.message.sender)?;
+    let owned = validate_shares(&deps, &owner, &denom, amount.u128())?;
+    let reserve = reserves_read(&deps.storage)
+        .may_load(denom.as_bytes())?
+        .unwrap_or_default()
+        .u128();
+    let total = total_shares_read(&deps.storage)
+        .may_load(denom.as_bytes())?
+        .unwrap_or_default()
+        .u128();
+    let pooled = reserve + held_reserve(&deps.storage, &denom)?;
+    let redeemed = shares_value(amount.u128(), pooled, total);
+    if redeemed > reserve {
+        return Err(StdError::generic_err(format!(
+            "insufficient reserve of {}({}), the rest is held for the pending bets",
+            denom, reserve
+        )));
+    }
+
+    shares(&mut deps.storage, &denom).save(owner.as_slice(), &Uint128(owned - amount.u128()))?;
+    total_shares(&mut deps.storage).save(denom.as_bytes(), &Uint128(total - amount.u128()))?;
+    reserves(&mut deps.storage).save(denom.as_bytes(), &Uint128(reserve - redeemed))?;
+
+    let messages = if redeemed > 0 {
+        vec![send_msg(
+            &deps.storage,
+            &env.contract.address,
+            env.message.sender,
+            &denom,
+            redeemed,
+        )?]
+    } else {
+        vec![]
+    };
+    Ok(HandleResponse {
+        messages,
+        log: vec![log("action", "withdrawn"), log("amount", redeemed)],
+        data: None,
+    })
+}
+
+pub fn try_rotate_seed<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    seed: String,
+) -> StdResult<HandleResponse> {
+    validate_admin(&deps, &env.message.sender)?;
+    // the entropy accumulated from the bettors is mixed in, so even the admin can't know the seed
+    let state: State = config_read(&deps.storage).load()?;
+    let seed = sha_256(&[seed.as_bytes(), &state.entropy].concat());
+    let epoch = rotate_epoch(&mut deps.storage, &seed)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "rotated"), log("epoch", epoch.id)],
+        data: Some(to_binary(&epoch.id)?),
+    })
+}
+
+pub fn try_set_bet_limits<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    denom: String,
+    limits: BetLimits,
+) -> StdResult<HandleResponse> {
+    validate_admin(&deps, &env.message.sender)?;
+    let limits = validate_bet_limits(limits)?;
+    bet_limits(&mut deps.storage).save(denom.as_bytes(), &limits)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "bet_limits_set"), log("denom", denom)],
+        data: None,
+    })
+}
+
+fn held_reserve<S: ReadonlyStorage>(storage: &S, denom: &str) -> StdResult<u128> {
+    Ok(held_reserves_read(storage)
+        .may_load(denom.as_bytes())?
+        .unwrap_or_default()
+        .u128())
+}
+
+// the first deposit mints a share per coin, the later ones are minted at the current share price
+fn shares_to_mint(amount: u128, reserve: u128, total_shares: u128) -> u128 {
+    if total_shares == 0 {
+        amount
+    } else {
+        amount * total_shares / reserve
+    }
+}
+
+fn shares_value(amount: u128, reserve: u128, total_shares: u128) -> u128 {
+    if total_shares == 0 {
+        0
+    } else {
+        amount * reserve / total_shares
+    }
+}
+
+pub fn try_generate_viewing_key<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    entropy: String,
+) -> StdResult<HandleResponse> {
+    let config: State = config_read(&deps.storage).load()?;
+    let key = ViewingKey::new(&env, &config.prng_seed, (&entropy).as_ref());
+
+    let message_sender = deps.api.canonical_address(&env.message.sender)?;
+    write_viewing_key(&mut deps.storage, &message_sender, &key);
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "generated")],
+        data: Some(to_binary(&key)?),
+    })
+}
+
+pub fn try_set_viewing_key<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    key: String,
+) -> StdResult<HandleResponse> {
+    let message_sender = deps.api.canonical_address(&env.message.sender)?;
+    write_viewing_key(&mut deps.storage, &message_sender, &ViewingKey(key));
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "set")],
+        data: None,
+    })
+}
+
+pub fn try_revoke_viewing_key<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    let message_sender = deps.api.canonical_address(&env.message.sender)?;
+    remove_viewing_key(&mut deps.storage, &message_sender);
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "revoked")],
+        data: None,
+    })
+}
+
+pub fn try_revoke_permit<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    permit_name: String,
+) -> StdResult<HandleResponse> {
+    let owner = deps.api.canonical_address(&env.message.sender)?;
+    revoked_permits(&mut deps.storage, &owner).save(permit_name.as_bytes(), &true)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "revoked")],
+        data: None,
+    })
+}
+
+pub fn try_update_config<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    fee_rate: Option<u64>,
+    fee_recipient: Option<HumanAddr>,
+    lp_fee_share: Option<u64>,
+    max_hands: Option<u32>,
+    max_nfts: Option<u32>,
+) -> StdResult<HandleResponse> {
+    let mut state = validate_admin(&deps, &env.message.sender)?;
+    if let Some(fee_rate) = fee_rate {
+        state.fee_rate = validate_fee_rate(fee_rate)?;
+    }
+    if let Some(fee_recipient) = fee_recipient {
+        state.fee_recipient = fee_recipient;
+    }
+    if let Some(lp_fee_share) = lp_fee_share {
+        state.lp_fee_share = validate_lp_fee_share(lp_fee_share)?;
+    }
+    if let Some(max_hands) = max_hands {
+        state.max_hands = validate_max_hands(max_hands)?;
+    }
+    if let Some(max_nfts) = max_nfts {
+        state.max_nfts = validate_max_nfts(max_nfts)?;
+    }
+    config(&mut deps.storage).save(&state)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "updated")],
+        data: None,
+    })
+}
+
+// the admin is not changed until the proposed address accepts it, so that the contract is never
+// handed over to a mistyped address
+pub fn try_change_admin<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    address: HumanAddr,
+) -> StdResult<HandleResponse> {
+    let mut state = validate_admin(&deps, &env.message.sender)?;
+    state.pending_admin = Some(address);
+    config(&mut deps.storage).save(&state)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "admin_proposed")],
+        data: None,
+    })
+}
+
+pub fn try_accept_admin<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    let mut state: State = config_read(&deps.storage).load()?;
+    if state.pending_admin.as_ref() != Some(&env.message.sender) {
+        return Err(StdError::unauthorized());
+    }
+    state.admin = env.message.sender;
+    state.pending_admin = None;
+    config(&mut deps.storage).save(&state)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "admin_accepted")],
+        data: None,
+    })
+}
+
+pub fn try_set_contract_status<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    level: ContractStatus,
+) -> StdResult<HandleResponse> {
+    let mut state = validate_admin(&deps, &env.message.sender)?;
+    state.contract_status = level;
+    config(&mut deps.storage).save(&state)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "status_set")],
+        data: None,
+    })
+}
+
+// upgrades the records of an older version in place. Secret Network v1.0 can't replace the code
+// of a contract, so this is unreachable for the contracts instantiated by the older versions
+pub fn try_migrate<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    let version = stored_version(&deps.storage)?;
+    if version == CONTRACT_VERSION {
+        return Err(StdError::generic_err(format!(
+            "already migrated to version({})",
+            version
+        )));
+    }
+
+    // each older version is upgraded to the current one at once
+    if version == v3::VERSION {
+        validate_admin(&deps, &env.message.sender)?;
+        migrate_v3(&mut deps.storage)?;
+    } else if version == v2::VERSION {
+        let state = load_v2_config(&deps.storage)?;
+        if env.message.sender != state.admin {
+            return Err(StdError::unauthorized());
+        }
+        migrate_v2(deps, &env.contract.address)?;
+    } else {
+        let state = load_v0_1_config(&deps.storage)?;
+        if env.message.sender != state.fee_recipient {
+            return Err(StdError::unauthorized());
+        }
+        migrate_v0_1(deps, &env.contract.address)?;
+    }
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "migrated"), log("version", CONTRACT_VERSION)],
+        data: None,
+    })
+}
+
+pub fn query<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    msg: QueryMsg,
+) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::Offer {
+            id,
+            address,
+            viewing_key,
+        } => match (address, viewing_key) {
+            (None, None) => query_public_offer(&deps, id),
+            (Some(address), Some(viewing_key)) => query_offer(
+                &deps,
+                QueryAuth::ViewingKey {
+                    address,
+                    viewing_key,
+                },
+                id,
+            ),
+            _ => Err(StdError::unauthorized()),
+        },
+        QueryMsg::Offers {
+            address,
+            viewing_key,
+            role,
+            status,
+            start_after,
+            limit,
+        } => query_offers(
+            &deps,
+            QueryAuth::ViewingKey {
+                address,
+                viewing_key,
+            },
+            role,
+            status,
+            start_after,
+            limit,
+        ),
+        QueryMsg::TokenBet {
+            id,
+            address,
+            viewing_key,
+        } => query_token_bet(
+            &deps,
+            QueryAuth::ViewingKey {
+                address,
+                viewing_key,
+            },
+            id,
+        ),
+        QueryMsg::MyTokenBets {
+            address,
+            viewing_key,
+            start_after,
+            limit,
+        } => query_my_token_bets(
+            &deps,
+            QueryAuth::ViewingKey {
+                address,
+                viewing_key,
+            },
+            start_after,
+            limit,
+        ),
+        QueryMsg::Config {} => query_config(&deps),
+        QueryMsg::ContractStatus {} => query_contract_status(&deps),
+        QueryMsg::FeeLedger { denom } => query_fee_ledger(&deps, denom),
+        QueryMsg::Shares {
+            address,
+            viewing_key,
+            denom,
+        } => query_shares(
+            &deps,
+            QueryAuth::ViewingKey {
+                address,
+                viewing_key,
+            },
+            denom,
+        ),
+        QueryMsg::BetLimits { denom } => query_bet_limits(&deps, denom),
+        QueryMsg::Epoch { id } => query_epoch(&deps, id),
+        QueryMsg::WithPermit { permit, query } => query_with_permit(&deps, permit, query),
+    }
+}
+
+fn query_with_permit<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    permit: Permit,
+    query: QueryWithPermit,
+) -> StdResult<Binary> {
+    let auth = QueryAuth::Permit(permit);
+    match query {
+        QueryWithPermit::Offer { id } => query_offer(&deps, auth, id),
+        QueryWithPermit::Offers {
+            role,
+            status,
+            start_after,
+            limit,
+        } => query_offers(&deps, auth, role, status, start_after, limit),
+        QueryWithPermit::TokenBet { id } => query_token_bet(&deps, auth, id),
+        QueryWithPermit::MyTokenBets { start_after, limit } => {
+            query_my_token_bets(&deps, auth, start_after, limit)
+        }
+        QueryWithPermit::Shares { denom } => query_shares(&deps, auth, denom),
+    }
+}
+
+// the offer details are seen only by the participants, and the others see the public view
+fn query_offer<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    auth: QueryAuth,
+    id: u64,
+) -> StdResult<Binary> {
+    let address = validate_query_auth(&deps, auth, Permission::Offers)?;
+    match offers_read(&deps.storage).may_load(&id.to_be_bytes())? {
+        Some(mut offer) if offer.offeror == address || offer.offeree == address => {
+            // the offeror hands are hidden against the offeree until accepted
+            if offer.offeror != address && offer.status != OfferStatus::Accepted {
+                offer.offeror_hands = Vec::<Hand>::new().into();
+            }
+            to_binary(&offer)
+        }
+        _ => Err(StdError::unauthorized()),
+    }
+}
+
+fn query_public_offer<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    id: u64,
+) -> StdResult<Binary> {
+    match offers_read(&deps.storage).may_load(&id.to_be_bytes())? {
+        Some(offer) => to_binary(&public_offer(offer)),
+        None => Err(StdError::generic_err(format!("offer({}) is not found", id))),
+    }
+}
+
+// the open offer shows its terms to the acceptors, the settled match shows the published sides
+fn public_offer(offer: Offer) -> PublicOfferResponse {
+    let terms = if offer.open && offer.status == OfferStatus::Offered {
+        Some(OpenOfferTerms {
+            offeror: offer.offeror.clone(),
+            offeror_nfts: offer.offeror_nfts.clone(),
+            offeror_funds: offer.offeror_funds.clone(),
+            offeree_collection: offer.offeree_collection.clone(),
+            offeree_funds: offer.offeree_funds.clone(),
+        })
+    } else {
+        None
+    };
+    let published = offer.offeror_published || offer.offeree_published;
+    let offeror = if offer.offeror_published {
+        Some(PublishedSide {
+            player: offer.offeror,
+            nfts: offer.offeror_nfts,
+            funds: offer.offeror_funds,
+            hands: offer.offeror_hands,
+        })
+    } else {
+        None
+    };
+    let offeree = if offer.offeree_published {
+        Some(PublishedSide {
+            player: offer.offeree,
+            nfts: offer.offeree_nfts,
+            funds: offer.offeree_funds,
+            hands: offer.offeree_hands,
+        })
+    } else {
+        None
+    };
+
+    PublicOfferResponse {
+        id: offer.id,
+        status: offer.status,
+        open: offer.open,
+        expires: offer.expires,
+        offeror_draw_point: if terms.is_some() || published {
+            Some(offer.offeror_draw_point)
+        } else {
+            None
+        },
+        terms,
+        offeror,
+        offeree,
+        winner: if published { Some(offer.winner) } else { None },
+    }
+}
+
+fn query_offers<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    auth: QueryAuth,
+    role: OfferRole,
+    status: Option<OfferStatus>,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<Binary> {
+    let address = validate_query_auth(&deps, auth, Permission::Offers)?;
+    let owner = deps.api.canonical_address(&address)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = range_start(start_after);
+
+    let index = match role {
+        OfferRole::Offeror => offeror_offers_read(&deps.storage, &owner),
+        OfferRole::Offeree => offeree_offers_read(&deps.storage, &owner),
+    };
+    let offers: StdResult<Vec<Offer>> = index
+        .range(start.as_deref(), None, Order::Ascending)
+        .map(|item| item.and_then(|(_, id)| offers_read(&deps.storage).load(&id.to_be_bytes())))
+        .filter(|item| match (item, &status) {
+            (Ok(offer), Some(status)) => &offer.status == status,
+            _ => true,
+        })
+        .take(limit)
+        .collect();
+
+    let mut offers = offers?;
+    // the offeror hands are hidden against the offeree until accepted
+    if role == OfferRole::Offeree {
+        for offer in offers.iter_mut() {
+            if offer.status != OfferStatus::Accepted {
+                offer.offeror_hands = Vec::<Hand>::new().into();
+            }
+        }
+    }
+    to_binary(&OffersResponse { offers })
+}
+
+// a missing bet is indistinguishable from the one of another bettor
+fn query_token_bet<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    auth: QueryAuth,
+    id: u64,
+) -> StdResult<Binary> {
+    let address = validate_query_auth(&deps, auth, Permission::TokenBets)?;
+    let owner = deps.api.canonical_address(&address)?;
+    match token_bets_read(&deps.storage).may_load(&id.to_be_bytes())? {
+        Some(bet) if bet.bettor == owner => {
+            to_binary(&hide_unrevealed_entropy(&deps.storage, bet)?)
+        }
+        _ => Err(StdError::unauthorized()),
+    }
+}
+
+// the entropy is the running state of the prng, which the seed holder could steer the next bets
+// by, so it's hidden until the seed of its epoch is revealed
+fn hide_unrevealed_entropy<S: Storage>(storage: &S, mut bet: TokenBet) -> StdResult<TokenBet> {
+    if bet.epoch > 0 {
+        let epoch = epochs_read(storage).load(&bet.epoch.to_be_bytes())?;
+        if epoch.seed.is_none() {
+            bet.entropy = Binary(vec![]);
+        }
+    }
+    Ok(bet)
+}
+
+fn query_my_token_bets<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    auth: QueryAuth,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<Binary> {
+    let address = validate_query_auth(&deps, auth, Permission::TokenBets)?;
+    let owner = deps.api.canonical_address(&address)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = range_start(start_after);
+
+    let bets: StdResult<Vec<TokenBet>> = bettor_bets_read(&deps.storage, &owner)
+        .range(start.as_deref(), None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            item.and_then(|(_, id)| token_bets_read(&deps.storage).load(&id.to_be_bytes()))
+                .and_then(|bet| hide_unrevealed_entropy(&deps.storage, bet))
+        })
+        .collect();
+    to_binary(&TokenBetsResponse { token_bets: bets? })
+}
+
+// the prng seed and the entropy are never exposed
+fn query_config<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<Binary> {
+    let state: State = config_read(&deps.storage).load()?;
+    to_binary(&ConfigResponse {
+        admin: state.admin,
+        pending_admin: state.pending_admin,
+        fee_recipient: state.fee_recipient,
+        fee_rate: state.fee_rate,
+        lp_fee_share: state.lp_fee_share,
+        max_hands: state.max_hands,
+        max_nfts: state.max_nfts,
+    })
+}
+
+fn query_contract_status<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<Binary> {
+    let state: State = config_read(&deps.storage).load()?;
+    to_binary(&ContractStatusResponse {
+        status: state.contract_status,
+    })
+}
+
+fn query_fee_ledger<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    denom: String,
+) -> StdResult<Binary> {
+    let accrued_fee = accrued_fees_read(&deps.storage)
+        .may_load(denom.as_bytes())?
+        .unwrap_or_default();
+    let reserve = reserves_read(&deps.storage)
+        .may_load(denom.as_bytes())?
+        .unwrap_or_default();
+    to_binary(&FeeLedgerResponse {
+        denom,
+        accrued_fee,
+        reserve,
+    })
+}
+
+fn query_shares<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    auth: QueryAuth,
+    denom: String,
+) -> StdResult<Binary> {
+    let address = validate_query_auth(&deps, auth, Permission::Shares)?;
+    let owner = deps.api.canonical_address(&address)?;
+    let owned = shares_read(&deps.storage, &denom)
+        .may_load(owner.as_slice())?
+        .unwrap_or_default();
+    let total = total_shares_read(&deps.storage)
+        .may_load(denom.as_bytes())?
+        .unwrap_or_default();
+    let reserve = reserves_read(&deps.storage)
+        .may_load(denom.as_bytes())?
+        .unwrap_or_default();
+    let pooled = reserve.u128() + held_reserve(&deps.storage, &denom)?;
+    to_binary(&SharesResponse {
+        amount: Uint128(shares_value(owned.u128(), pooled, total.u128())),
+        denom,
+        shares: owned,
+        total_shares: total,
+    })
+}
+
+fn query_bet_limits<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    denom: String,
+) -> StdResult<Binary> {
+    let limits = bet_limits_read(&deps.storage)
+        .may_load(denom.as_bytes())?
+        .unwrap_or_default();
+    let reserve = reserves_read(&deps.storage)
+        .may_load(denom.as_bytes())?
+        .unwrap_or_default();
+    to_binary(&BetLimitsResponse {
+        denom,
+        min_bet: limits.min_bet,
+        max_bet: limits.max_bet,
+        max_payout_rate: limits.max_payout_rate,
+        max_payout: Uint128(limits.max_payout(reserve.u128())),
+    })
+}
+
+fn query_epoch<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    id: Option<u64>,
+) -> StdResult<Binary> {
+    let id = match id {
+        Some(id) => id,
+        None => current_epoch_read(&deps.storage).load()?,
+    };
+    match epochs_read(&deps.storage).may_load(&id.to_be_bytes())? {
+        Some(epoch) => to_binary(&epoch),
+        None => Err(StdError::generic_err(format!("epoch({}) is not found", id))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hand::{Hand, Hands};
+    use crate::mock::{mock_dependencies, mock_permit, MockQuerier};
+    use crate::permit::{pub_key_to_address, PermitParams};
+    use crate::state::{pending_bets_read, Epoch};
+    use crate::utils::calculate_fee;
+    use cosmwasm_std::testing::{mock_env, MockApi, MockStorage, MOCK_CONTRACT_ADDR};
+    use cosmwasm_std::{from_binary, Coin, StdError};
+    use std::collections::HashMap;
+
+    fn initialize() -> Extern<MockStorage, MockApi, MockQuerier> {
+        let denom = "uscrt".to_string();
+        let init_amount = coins(10000, &denom);
+        let env = mock_env("deployer", &init_amount);
+        let contract_addr = env.contract.address.clone();
+
+        let owners = HashMap::from([
+            ("nft_id_1".to_string(), HumanAddr("nft_owner_1".to_string())),
+            ("nft_id_2".to_string(), HumanAddr("nft_owner_2".to_string())),
+            ("nft_id_3".to_string(), HumanAddr("nft_owner_3".to_string())),
+            ("nft_id_4".to_string(), HumanAddr("nft_owner_1".to_string())),
+        ]);
+        let balance: &[(&HumanAddr, &[Coin])] = &[
+            (&HumanAddr::from("deployer"), &init_amount),
+            (&contract_addr, &init_amount),
+            (&HumanAddr::from("bettor_1"), &init_amount),
+            (&HumanAddr::from("bettor_2"), &init_amount),
+        ];
+        let mut deps = mock_dependencies(balance, Some(owners));
+        let msg = InitMsg {
+            prng_seed: "prng_seed".to_string(),
+            fee_recipient: None,
+            fee_rate: None,
+        };
+        init(&mut deps, env.clone(), msg).unwrap();
+        handle(&mut deps, env, HandleMsg::Deposit {}).unwrap();
+        deps
+    }
+
+    // the code hash is named after the contract
+    fn nft(contract: &str, token_id: &str) -> Nft {
+        Nft {
+            contract: contract.into(),
+            code_hash: contract.replace("contract", "code_hash"),
+            token_id: token_id.to_string(),
+        }
+    }
+
+    fn valid_sample_offer_msg() -> HandleMsg {
+        HandleMsg::MakeOffer {
+            id: None,
+            offeree: Some("nft_owner_2".into()),
+            offeror_nfts: vec![nft("offeror_contract", "nft_id_1")],
+            offeree_nfts: Some(vec![nft("offeree_contract", "nft_id_2")]),
+            offeree_collection: None,
+            offeree_funds: None,
+            offeror_hands: vec![1, 2, 3],
+            offeror_draw_point: -1,
+            expires: None,
+            escrow: None,
+        }
+    }
+
+    fn assigned_id(res: &HandleResponse) -> u64 {
+        from_binary(res.data.as_ref().unwrap()).unwrap()
+    }
+
+    fn viewing_key(deps: &mut Extern<MockStorage, MockApi, MockQuerier>, sender: &str) -> String {
+        let msg = HandleMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+            padding: None,
+        };
+        let res = handle(deps, mock_env(sender, &[]), msg).unwrap();
+        from_binary(&res.data.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn proper_initialization() {
+        let mut deps = mock_dependencies(&[(&HumanAddr::from(""), &[])], None);
+        let msg = InitMsg {
+            prng_seed: "prng_seed".to_string(),
+            fee_recipient: None,
+            fee_rate: None,
+        };
+        init(&mut deps, mock_env("creator", &[]), msg).unwrap();
+    }
+
+    #[test]
+    fn update_config() {
+        let mut deps = initialize();
+
+        let msg = HandleMsg::UpdateConfig {
+            fee_rate: Some(500),
+            fee_recipient: Some("recipient".into()),
+            lp_fee_share: Some(2000),
+            max_hands: None,
+            max_nfts: Some(3),
+        };
+
+        // failed by non admin
+        let res = handle(&mut deps, mock_env("anyone", &[]), msg.clone());
+        assert_eq!(Some(StdError::unauthorized()), res.err());
+
+        // failed by too high fee rate
+        let invalid_msg = HandleMsg::UpdateConfig {
+            fee_rate: Some(INVERSE_BASIS_POINT + 1),
+            fee_recipient: None,
+            lp_fee_share: None,
+            max_hands: None,
+            max_nfts: None,
+        };
+        let res = handle(&mut deps, mock_env("deployer", &[]), invalid_msg);
+        assert_eq!(
+            Some(StdError::generic_err(format!(
+                "fee_rate({}) should not be more than {}",
+                INVERSE_BASIS_POINT + 1,
+                INVERSE_BASIS_POINT
+            ))),
+            res.err()
+        );
+
+        // failed by no hands or nfts allowed
+        let invalid_msg = HandleMsg::UpdateConfig {
+            fee_rate: None,
+            fee_recipient: None,
+            lp_fee_share: None,
+            max_hands: Some(0),
+            max_nfts: None,
+        };
+        let res = handle(&mut deps, mock_env("deployer", &[]), invalid_msg);
+        assert_eq!(
+            Some(StdError::generic_err(format!(
+                "max_hands(0) should be between 1 and {}",
+                MAX_HANDS_LIMIT
+            ))),
+            res.err()
+        );
+        let invalid_msg = HandleMsg::UpdateConfig {
+            fee_rate: None,
+            fee_recipient: None,
+            lp_fee_share: None,
+            max_hands: None,
+            max_nfts: Some(0),
+        };
+        let res = handle(&mut deps, mock_env("deployer", &[]), invalid_msg);
+        assert_eq!(
+            Some(StdError::generic_err(format!(
+                "max_nfts(0) should be between 1 and {}",
+                MAX_NFTS_LIMIT
+            ))),
+            res.err()
+        );
+
+        // failed by the draw point out of the range of i8
+        let invalid_msg = HandleMsg::UpdateConfig {
+            fee_rate: None,
+            fee_recipient: None,
+            lp_fee_share: None,
+            max_hands: Some(MAX_HANDS_LIMIT + 1),
+            max_nfts: None,
+        };
+        let res = handle(&mut deps, mock_env("deployer", &[]), invalid_msg);
+        assert_eq!(
+            Some(StdError::generic_err(format!(
+                "max_hands({}) should be between 1 and {}",
+                MAX_HANDS_LIMIT + 1,
+                MAX_HANDS_LIMIT
+            ))),
+            res.err()
+        );
+
+        // succeed
+        handle(&mut deps, mock_env("deployer", &[]), msg).unwrap();
+
+        // the admin is changed after accepted
+        let msg = HandleMsg::ChangeAdmin {
+            address: "new_admin".into(),
+        };
+        handle(&mut deps, mock_env("deployer", &[]), msg).unwrap();
+        let res = handle(
+            &mut deps,
+            mock_env("anyone", &[]),
+            HandleMsg::AcceptAdmin {},
+        );
+        assert_eq!(Some(StdError::unauthorized()), res.err());
+        handle(
+            &mut deps,
+            mock_env("new_admin", &[]),
+            HandleMsg::AcceptAdmin {},
+        )
+        .unwrap();
+
+        let res = query(&deps, QueryMsg::Config {}).unwrap();
+        let config: ConfigResponse = from_binary(&res).unwrap();
+        let expected = ConfigResponse {
+            admin: "new_admin".into(),
+            pending_admin: None,
+            fee_recipient: "recipient".into(),
+            fee_rate: 500,
+            lp_fee_share: 2000,
+            max_hands: DEFAULT_MAX_HANDS,
+            max_nfts: 3,
+        };
+        assert_eq!(expected, config);
+    }
+
+    #[test]
+    fn set_contract_status() {
+        let mut deps = initialize();
+
+        let env = mock_env("nft_owner_1", &[]);
+        let res = handle(&mut deps, env.clone(), valid_sample_offer_msg()).unwrap();
+        let offer_id = assigned_id(&res);
+        let status_msg = |level| HandleMsg::SetContractStatus { level };
+
+        // failed by non admin
+        let res = handle(
+            &mut deps,
+            mock_env("anyone", &[]),
+            status_msg(ContractStatus::StopAll),
+        );
+        assert_eq!(Some(StdError::unauthorized()), res.err());
+
+        // new games are stopped
+        let deployer = mock_env("deployer", &[]);
+        handle(
+            &mut deps,
+            deployer.clone(),
+            status_msg(ContractStatus::StopNewGames),
+        )
+        .unwrap();
+        let res = handle(&mut deps, env.clone(), valid_sample_offer_msg());
+        assert_eq!(
+            Some(StdError::generic_err(
+                "the contract is stopped for new games"
+            )),
+            res.err()
+        );
+        let msg = HandleMsg::BetToken {
+            id: None,
+            hand: 1,
+            entropy: "entropy".to_string(),
+            commit: None,
+        };
+        let res = handle(&mut deps, mock_env("bettor_1", &coins(100, "uscrt")), msg);
+        assert_eq!(
+            Some(StdError::generic_err(
+                "the contract is stopped for new games"
+            )),
+            res.err()
+        );
+
+        // everything but refunds and admin operations is stopped
+        handle(
+            &mut deps,
+            deployer.clone(),
+            status_msg(ContractStatus::StopAll),
+        )
+        .unwrap();
+        let msg = HandleMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+            padding: None,
+        };
+        let res = handle(&mut deps, env.clone(), msg);
+        assert_eq!(
+            Some(StdError::generic_err("the contract is stopped")),
+            res.err()
+        );
+        let msg = HandleMsg::CancelOffer { id: offer_id };
+        handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::ContractStatus {}).unwrap();
+        let res: ContractStatusResponse = from_binary(&res).unwrap();
+        assert_eq!(ContractStatus::StopAll, res.status);
+    }
+
+    #[test]
+    fn try_offer() {
+        let mut deps = initialize();
+
+        let env = mock_env("nft_owner_1", &[]);
+        let msg = valid_sample_offer_msg();
+
+        // succeed with assigned ids
+        let res = handle(&mut deps, env.clone(), msg.clone()).unwrap();
+        assert_eq!(1, assigned_id(&res));
+        let res = handle(&mut deps, env.clone(), msg).unwrap();
+        assert_eq!(2, assigned_id(&res));
+
+        // failed by duplicated client id
+        let msg = HandleMsg::MakeOffer {
+            id: Some(100),
+            offeree: Some("nft_owner_2".into()),
+            offeror_nfts: vec![nft("offeror_contract", "nft_id_1")],
+            offeree_nfts: Some(vec![nft("offeree_contract", "nft_id_2")]),
+            offeree_collection: None,
+            offeree_funds: None,
+            offeror_hands: vec![1, 2, 3],
+            offeror_draw_point: -1,
+            expires: None,
+            escrow: None,
+        };
+        let res = handle(&mut deps, env.clone(), msg.clone()).unwrap();
+        assert_eq!(3, assigned_id(&res));
+        let res = handle(&mut deps, env.clone(), msg);
+        assert_eq!(
+            Some(StdError::generic_err(
+                "duplicated id(100), already assigned to 3"
+            )),
+            res.err()
+        );
+
+        // the same client id is available for the other sender
+        let msg = HandleMsg::MakeOffer {
+            id: Some(100),
+            offeree: Some("nft_owner_1".into()),
+            offeror_nfts: vec![nft("offeree_contract", "nft_id_2")],
+            offeree_nfts: Some(vec![nft("offeror_contract", "nft_id_1")]),
+            offeree_collection: None,
+            offeree_funds: None,
+            offeror_hands: vec![1, 2, 3],
+            offeror_draw_point: -1,
+            expires: None,
+            escrow: None,
+        };
+        let res = handle(&mut deps, mock_env("nft_owner_2", &[]), msg).unwrap();
+        assert_eq!(4, assigned_id(&res));
+
+        // failed by invalid nft_id
+        let msg = HandleMsg::MakeOffer {
+            id: None,
+            offeree: Some("nft_owner_2".into()),
+            offeror_nfts: vec![nft("contract", "invalid_nft_id")],
+            offeree_nfts: Some(vec![nft("contract", "nft_id_2")]),
+            offeree_collection: None,
+            offeree_funds: None,
+            offeror_hands: vec![1, 2, 3],
+            offeror_draw_point: 2,
+            expires: None,
+            escrow: None,
+        };
+
+        let res = handle(&mut deps, env.clone(), msg);
+        assert_eq!(true, res.is_err());
+
+        // failed by passed expiration
+        let msg = HandleMsg::MakeOffer {
+            id: None,
+            offeree: Some("nft_owner_2".into()),
+            offeror_nfts: vec![nft("offeror_contract", "nft_id_1")],
+            offeree_nfts: Some(vec![nft("offeree_contract", "nft_id_2")]),
+            offeree_collection: None,
+            offeree_funds: None,
+            offeror_hands: vec![1, 2, 3],
+            offeror_draw_point: -1,
+            expires: Some(Expiration::AtHeight(env.block.height)),
+            escrow: None,
+        };
+        let res = handle(&mut deps, env, msg);
+        assert_eq!(
+            Some(StdError::generic_err(format!(
+                "expiration is already passed({:?})",
+                Expiration::AtHeight(12_345)
+            ))),
+            res.err()
+        );
+    }
+
+    #[test]
+    fn try_offer_invalid_hands() {
+        let mut deps = initialize();
+        let env = mock_env("nft_owner_1", &[]);
+
+        let offer_msg = |hands: Vec<u8>, draw_point: i8| HandleMsg::MakeOffer {
+            id: None,
+            offeree: Some("nft_owner_2".into()),
+            offeror_nfts: vec![nft("offeror_contract", "nft_id_1")],
+            offeree_nfts: Some(vec![nft("offeree_contract", "nft_id_2")]),
+            offeree_collection: None,
+            offeree_funds: None,
+            offeror_hands: hands,
+            offeror_draw_point: draw_point,
+            expires: None,
+            escrow: None,
+        };
+
+        // failed by empty hands
+        let res = handle(&mut deps, env.clone(), offer_msg(vec![], 0));
+        assert_eq!(Some(StdError::generic_err("hands are empty")), res.err());
+
+        // failed by too many hands
+        let max_hands = DEFAULT_MAX_HANDS as usize;
+        let res = handle(&mut deps, env.clone(), offer_msg(vec![1; max_hands + 1], 0));
+        assert_eq!(
+            Some(StdError::generic_err(format!(
+                "too many hands({}), max: {}",
+                max_hands + 1,
+                max_hands
+            ))),
+            res.err()
+        );
+
+        // failed by out of range hand
+        let res = handle(&mut deps, env.clone(), offer_msg(vec![1, 4, 3], 0));
+        assert_eq!(
+            Some(StdError::generic_err(
+                "invalid hand(4), should be 1(Rock), 2(Paper) or 3(Scissors)"
+            )),
+            res.err()
+        );
+
+        // failed by out of range draw point
+        let res = handle(&mut deps, env.clone(), offer_msg(vec![1, 2, 3], -3));
+        assert_eq!(
+            Some(StdError::generic_err(
+                "offeror_draw_point(-3) is out of range, should be between -2 and 2"
+            )),
+            res.err()
+        );
+
+        // failed by mismatched offeree hands length
+        handle(&mut deps, env, offer_msg(vec![1, 2, 3], 0)).unwrap();
+        let msg = HandleMsg::AcceptOffer {
+            id: 1,
+            offeree_hands: vec![1, 2],
+            offeree_nft: None,
+        };
+        let res = handle(&mut deps, mock_env("nft_owner_2", &[]), msg);
+        assert_eq!(
+            Some(StdError::generic_err(
+                "offeree hands length(2) is not same as offeror hands length(3)"
+            )),
+            res.err()
+        );
+    }
+
+    #[test]
+    fn try_accept() {
+        let mut deps = initialize();
+
+        let env = mock_env("nft_owner_1", &[]);
+        let msg = valid_sample_offer_msg();
+        let res = handle(&mut deps, env, msg).unwrap();
+        let offer_id = assigned_id(&res);
+
+        let msg = HandleMsg::AcceptOffer {
+            id: offer_id,
+            offeree_hands: vec![3, 2, 1],
+            offeree_nft: None,
+        };
+
+        // faild by invalid sender
+        let env = mock_env("invalid_sender", &[]);
+        let res = handle(&mut deps, env, msg.clone());
+        assert_eq!(
+            Some(StdError::generic_err(
+                "msg sender is not offeree(nft_owner_2)"
+            )),
+            res.err()
+        );
+
+        // succeed
+        let env = mock_env("nft_owner_2", &[]);
+        let res = handle(&mut deps, env, msg).unwrap();
+        assert_eq!(1, res.messages.len());
+
+        let transfer_msg = to_binary(&Cw721HandleMsg::TransferNft {
+            recipient: "nft_owner_1".into(),
+            token_id: "nft_id_2".to_string(),
+            memo: None,
+            padding: None,
+        })
+        .unwrap();
+        let msg: CosmosMsg = WasmMsg::Execute {
+            contract_addr: "offeree_contract".into(),
+            callback_code_hash: "offeree_code_hash".to_string(),
+            msg: transfer_msg,
+            send: vec![],
+        }
+        .into();
+        assert_eq!(msg, res.messages[0]);
+
+        // failed by already accepted
+        let env = mock_env("nft_owner_2", &[]);
+        let msg = HandleMsg::AcceptOffer {
+            id: offer_id,
+            offeree_hands: vec![3, 2, 1],
+            offeree_nft: None,
+        };
+        let res = handle(&mut deps, env, msg);
+        assert_eq!(
+            Some(StdError::generic_err(format!(
+                "offer({}) is already closed, status: {:?}",
+                offer_id,
+                OfferStatus::Accepted
+            ))),
+            res.err()
+        );
+    }
+
+    #[test]
+    fn try_accept_open_offer() {
+        let mut deps = initialize();
+
+        let open_offer_msg = |offeree: Option<HumanAddr>| HandleMsg::MakeOffer {
+            id: None,
+            offeree,
+            offeror_nfts: vec![nft("offeror_contract", "nft_id_1")],
+            offeree_nfts: None,
+            offeree_collection: Some(NftCollection {
+                contract: "offeree_contract".into(),
+                code_hash: "offeree_code_hash".to_string(),
+                whitelist: vec!["nft_id_2".to_string(), "nft_id_3".to_string()],
+            }),
+            offeree_funds: None,
+            offeror_hands: vec![1, 2, 3],
+            offeror_draw_point: -1,
+            expires: None,
+            escrow: None,
+        };
+
+        // failed by specifying offeree without offeree nfts
+        let env = mock_env("nft_owner_1", &[]);
+        let res = handle(
+            &mut deps,
+            env.clone(),
+            open_offer_msg(Some("nft_owner_2".into())),
+        );
+        assert_eq!(
+            Some(StdError::generic_err(
+                "specify both offeree and offeree_nfts, or only offeree_collection for open offer"
+            )),
+            res.err()
+        );
+
+        let res = handle(&mut deps, env, open_offer_msg(None)).unwrap();
+        let offer_id = assigned_id(&res);
+
+        // the terms are public while anyone can accept
+        let public_query = QueryMsg::Offer {
+            id: offer_id,
+            address: None,
+            viewing_key: None,
+        };
+        let res = query(&deps, public_query.clone()).unwrap();
+        let public: PublicOfferResponse = from_binary(&res).unwrap();
+        let terms = public.terms.unwrap();
+        assert_eq!(HumanAddr::from("nft_owner_1"), terms.offeror);
+        assert_eq!(
+            vec![nft("offeror_contract", "nft_id_1")],
+            terms.offeror_nfts
+        );
+        assert!(terms.offeree_collection.is_some());
+        assert_eq!(Some(-1), public.offeror_draw_point);
+
+        // failed by decline
+        let msg = HandleMsg::DeclineOffer { id: offer_id };
+        let res = handle(&mut deps, mock_env("nft_owner_3", &[]), msg);
+        assert_eq!(
+            Some(StdError::generic_err(format!(
+                "open offer({}) can't be declined",
+                offer_id
+            ))),
+            res.err()
+        );
+
+        let accept_msg = |offeree_nft: Option<&str>| HandleMsg::AcceptOffer {
+            id: offer_id,
+            offeree_hands: vec![3, 2, 1],
+            offeree_nft: offeree_nft.map(|nft| nft.to_string()),
+        };
+
+        // failed by missing nft
+        let res = handle(&mut deps, mock_env("nft_owner_3", &[]), accept_msg(None));
+        assert_eq!(
+            Some(StdError::generic_err(
+                "offeree_nft is required for open offer"
+            )),
+            res.err()
+        );
+
+        // failed by not whitelisted nft
+        let res = handle(
+            &mut deps,
+            mock_env("nft_owner_3", &[]),
+            accept_msg(Some("nft_id_1")),
+        );
+        assert_eq!(
+            Some(StdError::generic_err(
+                "nft(nft_id_1) is not in the whitelist"
+            )),
+            res.err()
+        );
+
+        // failed by the nft owned by the other
+        let res = handle(
+            &mut deps,
+            mock_env("nft_owner_3", &[]),
+            accept_msg(Some("nft_id_2")),
+        );
+        assert_eq!(true, res.is_err());
+
+        // the acceptor becomes the offeree
+        let res = handle(
+            &mut deps,
+            mock_env("nft_owner_3", &[]),
+            accept_msg(Some("nft_id_3")),
+        );
+        let expected =
+            transfer_nft_msg(&nft("offeree_contract", "nft_id_3"), "nft_owner_1".into()).unwrap();
+        assert_eq!(vec![expected], res.unwrap().messages);
+
+        let offer = offers_read(&deps.storage)
+            .load(&offer_id.to_be_bytes())
+            .unwrap();
+        assert_eq!(HumanAddr::from("nft_owner_3"), offer.offeree);
+        assert_eq!(
+            vec![nft("offeree_contract", "nft_id_3")],
+            offer.offeree_nfts
+        );
+
+        // the terms are hidden once accepted
+        let res = query(&deps, public_query).unwrap();
+        let public: PublicOfferResponse = from_binary(&res).unwrap();
+        assert_eq!(None, public.terms);
+        assert_eq!(None, public.offeror_draw_point);
+    }
+
+    #[test]
+    fn try_accept_bundle() {
+        let mut deps = initialize();
+
+        let bundle_offer_msg = |offeror_nfts: Vec<Nft>| HandleMsg::MakeOffer {
+            id: None,
+            offeree: Some("nft_owner_2".into()),
+            offeror_nfts,
+            offeree_nfts: Some(vec![nft("offeree_contract", "nft_id_2")]),
+            offeree_collection: None,
+            offeree_funds: None,
+            offeror_hands: vec![1, 2, 3],
+            offeror_draw_point: -1,
+            expires: None,
+            escrow: None,
+        };
+        let env = mock_env("nft_owner_1", &[]);
+
+        // failed by staking nothing
+        let res = handle(&mut deps, env.clone(), bundle_offer_msg(vec![]));
+        assert_eq!(
+            Some(StdError::generic_err("neither nfts nor funds are staked")),
+            res.err()
+        );
+
+        // failed by duplicated nft
+        let msg = bundle_offer_msg(vec![
+            nft("offeror_contract", "nft_id_1"),
+            nft("offeror_contract", "nft_id_1"),
+        ]);
+        let res = handle(&mut deps, env.clone(), msg);
+        assert_eq!(
+            Some(StdError::generic_err(
+                "duplicated nft(nft_id_1) of offeror_contract"
+            )),
+            res.err()
+        );
+
+        // failed by the nft owned by the other
+        let msg = bundle_offer_msg(vec![
+            nft("offeror_contract", "nft_id_1"),
+            nft("offeror_contract", "nft_id_3"),
+        ]);
+        let res = handle(&mut deps, env.clone(), msg);
+        assert_eq!(true, res.is_err());
+
+        // the offeree wins and obtains every nft of the bundle
+        let msg = bundle_offer_msg(vec![
+            nft("offeror_contract", "nft_id_1"),
+            nft("offeror_contract", "nft_id_4"),
+        ]);
+        let res = handle(&mut deps, env, msg).unwrap();
+        let msg = HandleMsg::AcceptOffer {
+            id: assigned_id(&res),
+            offeree_hands: vec![2, 3, 1],
+            offeree_nft: None,
+        };
+        let res = handle(&mut deps, mock_env("nft_owner_2", &[]), msg).unwrap();
+        let expected = vec![
+            transfer_nft_msg(&nft("offeror_contract", "nft_id_1"), "nft_owner_2".into()).unwrap(),
+            transfer_nft_msg(&nft("offeror_contract", "nft_id_4"), "nft_owner_2".into()).unwrap(),
+        ];
+        assert_eq!(expected, res.messages);
+    }
+
+    #[test]
+    fn try_accept_funds() {
+        let mut deps = initialize();
+
+        let env = mock_env("nft_owner_1", &coins(100, "uscrt"));
+        let contract_addr = env.contract.address.clone();
+        let msg = HandleMsg::MakeOffer {
+            id: None,
+            offeree: Some("nft_owner_2".into()),
+            offeror_nfts: vec![nft("offeror_contract", "nft_id_1")],
+            offeree_nfts: Some(vec![nft("offeree_contract", "nft_id_2")]),
+            offeree_collection: None,
+            offeree_funds: Some(coins(50, "uscrt")),
+            offeror_hands: vec![1, 2, 3],
+            offeror_draw_point: -1,
+            expires: None,
+            escrow: None,
+        };
+        let res = handle(&mut deps, env.clone(), msg.clone()).unwrap();
+        let offer_id = assigned_id(&res);
+
+        // failed by not sending the requested funds
+        let accept_msg = HandleMsg::AcceptOffer {
+            id: offer_id,
+            offeree_hands: vec![2, 3, 1],
+            offeree_nft: None,
+        };
+        let res = handle(
+            &mut deps,
+            mock_env("nft_owner_2", &coins(10, "uscrt")),
+            accept_msg.clone(),
+        );
+        assert_eq!(
+            Some(StdError::generic_err(format!(
+                "sent funds({:?}) are not the requested ones({:?})",
+                coins(10, "uscrt"),
+                coins(50, "uscrt")
+            ))),
+            res.err()
+        );
+
+        // the offeree wins and obtains both funds
+        let res = handle(
+            &mut deps,
+            mock_env("nft_owner_2", &coins(50, "uscrt")),
+            accept_msg,
+        )
+        .unwrap();
+        let expected = vec![
+            transfer_nft_msg(&nft("offeror_contract", "nft_id_1"), "nft_owner_2".into()).unwrap(),
+            CosmosMsg::Bank(BankMsg::Send {
+                from_address: contract_addr.clone(),
+                to_address: "nft_owner_2".into(),
+                amount: coins(100, "uscrt"),
+            }),
+            CosmosMsg::Bank(BankMsg::Send {
+                from_address: contract_addr.clone(),
+                to_address: "nft_owner_2".into(),
+                amount: coins(50, "uscrt"),
+            }),
+        ];
+        assert_eq!(expected, res.messages);
+
+        // the offeror funds are refunded on cancel
+        let res = handle(&mut deps, env, msg).unwrap();
+        let msg = HandleMsg::CancelOffer {
+            id: assigned_id(&res),
+        };
+        let res = handle(&mut deps, mock_env("nft_owner_1", &[]), msg).unwrap();
+        let expected = vec![CosmosMsg::Bank(BankMsg::Send {
+            from_address: contract_addr,
+            to_address: "nft_owner_1".into(),
+            amount: coins(100, "uscrt"),
+        })];
+        assert_eq!(expected, res.messages);
+    }
+
+    #[test]
+    fn try_accept_token_stake() {
+        let mut deps = initialize();
+
+        let env = mock_env("nft_owner_1", &[]);
+        let contract_addr = env.contract.address.clone();
+        let token_offer_msg = |offeror_nfts: Vec<Nft>| HandleMsg::MakeOffer {
+            id: None,
+            offeree: Some("nft_owner_2".into()),
+            offeror_nfts,
+            offeree_nfts: Some(vec![]),
+            offeree_collection: None,
+            offeree_funds: Some(coins(1000, "uscrt")),
+            offeror_hands: vec![1, 2, 3],
+            offeror_draw_point: -1,
+            expires: None,
+            escrow: None,
+        };
+
+        // failed by staking no nft on both sides
+        let res = handle(
+            &mut deps,
+            mock_env("nft_owner_1", &coins(1000, "uscrt")),
+            token_offer_msg(vec![]),
+        );
+        assert_eq!(
+            Some(StdError::generic_err("either side should stake nfts")),
+            res.err()
+        );
+
+        let offeror_nfts = vec![nft("offeror_contract", "nft_id_1")];
+        let res = handle(
+            &mut deps,
+            env.clone(),
+            token_offer_msg(offeror_nfts.clone()),
+        )
+        .unwrap();
+        let offer_id = assigned_id(&res);
+        let res = handle(&mut deps, env, token_offer_msg(offeror_nfts)).unwrap();
+        let next_offer_id = assigned_id(&res);
+
+        // the coin staker wins, then obtains the nft and the own coins back
+        let msg = HandleMsg::AcceptOffer {
+            id: offer_id,
+            offeree_hands: vec![2, 3, 1],
+            offeree_nft: None,
+        };
+        let res = handle(
+            &mut deps,
+            mock_env("nft_owner_2", &coins(1000, "uscrt")),
+            msg,
+        )
+        .unwrap();
+        let expected = vec![
+            transfer_nft_msg(&nft("offeror_contract", "nft_id_1"), "nft_owner_2".into()).unwrap(),
+            CosmosMsg::Bank(BankMsg::Send {
+                from_address: contract_addr.clone(),
+                to_address: "nft_owner_2".into(),
+                amount: coins(1000, "uscrt"),
+            }),
+        ];
+        assert_eq!(expected, res.messages);
+
+        // the nft owner wins, then obtains the coins minus fee
+        let msg = HandleMsg::AcceptOffer {
+            id: next_offer_id,
+            offeree_hands: vec![3, 2, 1],
+            offeree_nft: None,
+        };
+        let res = handle(
+            &mut deps,
+            mock_env("nft_owner_2", &coins(1000, "uscrt")),
+            msg,
+        )
+        .unwrap();
+        let fee = calculate_fee(1000, DEFAULT_FEE_RATE);
+        let expected = vec![CosmosMsg::Bank(BankMsg::Send {
+            from_address: contract_addr,
+            to_address: "nft_owner_1".into(),
+            amount: coins(1000 - fee, "uscrt"),
+        })];
+        assert_eq!(expected, res.messages);
+    }
+
+    #[test]
+    fn try_accept_escrow() {
+        let mut deps = initialize();
+
+        let env = mock_env("nft_owner_1", &[]);
+        let contract_addr = env.contract.address.clone();
+        let msg = HandleMsg::MakeOffer {
+            id: None,
+            offeree: Some("nft_owner_2".into()),
+            offeror_nfts: vec![nft("offeror_contract", "nft_id_1")],
+            offeree_nfts: Some(vec![nft("offeree_contract", "nft_id_2")]),
+            offeree_collection: None,
+            offeree_funds: None,
+            offeror_hands: vec![1, 2, 3],
+            offeror_draw_point: -1,
+            expires: None,
+            escrow: Some(true),
+        };
+
+        // offeror nft is pulled into contract
+        let res = handle(&mut deps, env, msg).unwrap();
+        let offer_id = assigned_id(&res);
+        let expected =
+            transfer_nft_msg(&nft("offeror_contract", "nft_id_1"), contract_addr.clone()).unwrap();
+        assert_eq!(vec![expected], res.messages);
+
+        // offeree nft is pulled into contract, then both are paid out to the winner
+        let msg = HandleMsg::AcceptOffer {
+            id: offer_id,
+            offeree_hands: vec![3, 2, 1],
+            offeree_nft: None,
+        };
+        let res = handle(&mut deps, mock_env("nft_owner_2", &[]), msg).unwrap();
+        let expected = vec![
+            transfer_nft_msg(&nft("offeree_contract", "nft_id_2"), contract_addr).unwrap(),
+            transfer_nft_msg(&nft("offeror_contract", "nft_id_1"), "nft_owner_1".into()).unwrap(),
+            transfer_nft_msg(&nft("offeree_contract", "nft_id_2"), "nft_owner_1".into()).unwrap(),
+        ];
+        assert_eq!(expected, res.messages);
+    }
+
+    #[test]
+    fn try_cancel_escrow() {
+        let mut deps = initialize();
+
+        let env = mock_env("nft_owner_1", &[]);
+        let msg = HandleMsg::MakeOffer {
+            id: None,
+            offeree: Some("nft_owner_2".into()),
+            offeror_nfts: vec![nft("offeror_contract", "nft_id_1")],
+            offeree_nfts: Some(vec![nft("offeree_contract", "nft_id_2")]),
+            offeree_collection: None,
+            offeree_funds: None,
+            offeror_hands: vec![1, 2, 3],
+            offeror_draw_point: -1,
+            expires: None,
+            escrow: Some(true),
+        };
+        let res = handle(&mut deps, env.clone(), msg).unwrap();
+        let offer_id = assigned_id(&res);
+
+        // escrowed nft is returned to offeror
+        let msg = HandleMsg::CancelOffer { id: offer_id };
+        let res = handle(&mut deps, env, msg).unwrap();
+        let expected =
+            transfer_nft_msg(&nft("offeror_contract", "nft_id_1"), "nft_owner_1".into()).unwrap();
+        assert_eq!(vec![expected], res.messages);
+    }
+
+    #[test]
+    fn receive_nft() {
+        let mut deps = initialize();
+
+        let msg = HandleMsg::RegisterReceiveNft {
+            nft_contract: "offeror_contract".into(),
+            code_hash: "offeror_code_hash".to_string(),
+        };
+        // failed by non admin
+        let res = handle(&mut deps, mock_env("anyone", &[]), msg.clone());
+        assert_eq!(Some(StdError::unauthorized()), res.err());
+
+        let res = handle(&mut deps, mock_env("deployer", &[]), msg).unwrap();
+        assert_eq!(1, res.messages.len());
+        let msg = HandleMsg::RegisterReceiveNft {
+            nft_contract: "offeree_contract".into(),
+            code_hash: "offeree_code_hash".to_string(),
+        };
+        handle(&mut deps, mock_env("deployer", &[]), msg).unwrap();
+
+        // failed by unregistered contract
+        let make_offer = to_binary(&ReceiveNftMsg::MakeOffer {
+            id: None,
+            offeree: Some("nft_owner_2".into()),
+            offeree_nfts: Some(vec![nft("offeree_contract", "nft_id_2")]),
+            offeree_collection: None,
+            offeree_funds: None,
+            offeror_hands: vec![1, 2, 3],
+            offeror_draw_point: -1,
+            expires: None,
+        })
+        .unwrap();
+        let msg = HandleMsg::ReceiveNft {
+            sender: "nft_owner_1".into(),
+            token_id: "nft_id_1".to_string(),
+            msg: Some(make_offer.clone()),
+        };
+        let res = handle(&mut deps, mock_env("unknown_contract", &[]), msg.clone());
+        assert_eq!(
+            Some(StdError::generic_err(
+                "unregistered nft contract(unknown_contract)"
+            )),
+            res.err()
+        );
+
+        // offer is made with the received nft in escrow
+        let res = handle(&mut deps, mock_env("offeror_contract", &[]), msg).unwrap();
+        assert_eq!(0, res.messages.len());
+        let offer_id = assigned_id(&res);
+        let offer = offers_read(&deps.storage)
+            .load(&offer_id.to_be_bytes())
+            .unwrap();
+        assert_eq!(HumanAddr::from("nft_owner_1"), offer.offeror);
+        assert_eq!(true, offer.escrow);
+
+        // failed by sending the other nft
+        let accept_offer = to_binary(&ReceiveNftMsg::AcceptOffer {
+            id: offer_id,
+            offeree_hands: vec![3, 2, 1],
+        })
+        .unwrap();
+        let msg = HandleMsg::ReceiveNft {
+            sender: "nft_owner_2".into(),
+            token_id: "nft_id_3".to_string(),
+            msg: Some(accept_offer.clone()),
+        };
+        let res = handle(&mut deps, mock_env("offeree_contract", &[]), msg);
+        assert_eq!(true, res.is_err());
+
+        // accepted with the received nft, both are paid out to the winner
+        let msg = HandleMsg::BatchReceiveNft {
+            sender: "nft_owner_2".into(),
+            from: "nft_owner_2".into(),
+            token_ids: vec!["nft_id_2".to_string()],
+            msg: Some(accept_offer),
+        };
+        let res = handle(&mut deps, mock_env("offeree_contract", &[]), msg).unwrap();
+        let expected = vec![
+            transfer_nft_msg(&nft("offeror_contract", "nft_id_1"), "nft_owner_1".into()).unwrap(),
+            transfer_nft_msg(&nft("offeree_contract", "nft_id_2"), "nft_owner_1".into()).unwrap(),
+        ];
+        assert_eq!(expected, res.messages);
+    }
+
+    #[test]
+    fn try_accept_expired() {
+        let mut deps = initialize();
+
+        let env = mock_env("nft_owner_1", &[]);
+        let expires = env.block.height + 10;
+        let msg = HandleMsg::MakeOffer {
+            id: None,
+            offeree: Some("nft_owner_2".into()),
+            offeror_nfts: vec![nft("offeror_contract", "nft_id_1")],
+            offeree_nfts: Some(vec![nft("offeree_contract", "nft_id_2")]),
+            offeree_collection: None,
+            offeree_funds: None,
+            offeror_hands: vec![1, 2, 3],
+            offeror_draw_point: -1,
+            expires: Some(Expiration::AtHeight(expires)),
+            escrow: None,
+        };
+        let res = handle(&mut deps, env, msg).unwrap();
+        let offer_id = assigned_id(&res);
+
+        // expired instead of matched
+        let mut env = mock_env("nft_owner_2", &[]);
+        env.block.height = expires;
+        let msg = HandleMsg::AcceptOffer {
+            id: offer_id,
+            offeree_hands: vec![3, 2, 1],
+            offeree_nft: None,
+        };
+        let res = handle(&mut deps, env.clone(), msg.clone()).unwrap();
+        assert_eq!(0, res.messages.len());
+        assert_eq!("expired", res.log[0].value);
+
+        let offer = offers_read(&deps.storage)
+            .load(&offer_id.to_be_bytes())
+            .unwrap();
+        assert_eq!(OfferStatus::Expired, offer.status);
+
+        // failed by expired status
+        let res = handle(&mut deps, env, msg);
+        assert_eq!(
+            Some(StdError::generic_err(format!(
+                "offer({}) is already closed, status: {:?}",
+                offer_id,
+                OfferStatus::Expired
+            ))),
+            res.err()
+        );
+    }
+
+    #[test]
+    fn try_cancel() {
+        let mut deps = initialize();
+
+        let env = mock_env("nft_owner_1", &[]);
+        let msg = valid_sample_offer_msg();
+        let res = handle(&mut deps, env.clone(), msg).unwrap();
+        let offer_id = assigned_id(&res);
+
+        // failed by invalid sender
+        let msg = HandleMsg::CancelOffer { id: offer_id };
+        let res = handle(&mut deps, mock_env("nft_owner_2", &[]), msg.clone());
+        assert_eq!(
+            Some(StdError::generic_err(
+                "msg sender is not offeror(nft_owner_1)"
+            )),
+            res.err()
+        );
+
+        // succeed
+        let res = handle(&mut deps, env, msg).unwrap();
+        assert_eq!("cancelled", res.log[0].value);
+
+        // failed by cancelled status
+        let msg = HandleMsg::AcceptOffer {
+            id: offer_id,
+            offeree_hands: vec![3, 2, 1],
+            offeree_nft: None,
+        };
+        let res = handle(&mut deps, mock_env("nft_owner_2", &[]), msg);
+        assert_eq!(
+            Some(StdError::generic_err(format!(
+                "offer({}) is already closed, status: {:?}",
+                offer_id,
+                OfferStatus::Cancelled
+            ))),
+            res.err()
+        );
+    }
+
+    #[test]
+    fn try_counter_offer() {
+        let mut deps = initialize();
+
+        let env = mock_env("nft_owner_1", &[]);
+        let res = handle(&mut deps, env.clone(), valid_sample_offer_msg()).unwrap();
+        let offer_id = assigned_id(&res);
+
+        let msg = HandleMsg::CounterOffer {
+            id: offer_id,
+            offeror_nfts: None,
+            offeree_nfts: Some(vec![nft("offeror_contract", "nft_id_4")]),
+            offeree_funds: None,
+            offeror_hands: vec![2, 2, 2],
+            offeror_draw_point: 0,
+            expires: None,
+            escrow: None,
+        };
+
+        // failed by invalid sender
+        let res = handle(&mut deps, env, msg.clone());
+        assert_eq!(
+            Some(StdError::generic_err(
+                "msg sender is not offeree(nft_owner_2)"
+            )),
+            res.err()
+        );
+
+        // succeed
+        let res = handle(&mut deps, mock_env("nft_owner_2", &[]), msg).unwrap();
+        let counter_id = assigned_id(&res);
+
+        let offer = offers_read(&deps.storage)
+            .load(&offer_id.to_be_bytes())
+            .unwrap();
+        assert_eq!(OfferStatus::Countered, offer.status);
+        assert_eq!(Some(counter_id), offer.countered_by);
+
+        let counter_offer = offers_read(&deps.storage)
+            .load(&counter_id.to_be_bytes())
+            .unwrap();
+        assert_eq!(Some(offer_id), counter_offer.counter_of);
+        assert_eq!(HumanAddr::from("nft_owner_2"), counter_offer.offeror);
+        assert_eq!(HumanAddr::from("nft_owner_1"), counter_offer.offeree);
+        assert_eq!(
+            vec![nft("offeree_contract", "nft_id_2")],
+            counter_offer.offeror_nfts
+        );
+        assert_eq!(
+            vec![nft("offeror_contract", "nft_id_4")],
+            counter_offer.offeree_nfts
+        );
+        assert_eq!(0, counter_offer.offeror_draw_point);
+
+        // failed by accepting the countered offer
+        let msg = HandleMsg::AcceptOffer {
+            id: offer_id,
+            offeree_hands: vec![3, 2, 1],
+            offeree_nft: None,
+        };
+        let res = handle(&mut deps, mock_env("nft_owner_2", &[]), msg);
+        assert_eq!(true, res.is_err());
+
+        // the original offeror accepts with new hands
+        let msg = HandleMsg::AcceptOffer {
+            id: counter_id,
+            offeree_hands: vec![3, 3, 3],
+            offeree_nft: None,
+        };
+        let res = handle(&mut deps, mock_env("nft_owner_1", &[]), msg).unwrap();
+        let expected =
+            transfer_nft_msg(&nft("offeree_contract", "nft_id_2"), "nft_owner_1".into()).unwrap();
+        assert_eq!(vec![expected], res.messages);
+    }
+
+    #[test]
+    fn try_counter_offer_escrow() {
+        let mut deps = initialize();
+
+        let env = mock_env("nft_owner_1", &[]);
+        let contract_addr = env.contract.address.clone();
+        let msg = HandleMsg::MakeOffer {
+            id: None,
+            offeree: Some("nft_owner_2".into()),
+            offeror_nfts: vec![nft("offeror_contract", "nft_id_1")],
+            offeree_nfts: Some(vec![nft("offeree_contract", "nft_id_2")]),
+            offeree_collection: None,
+            offeree_funds: None,
+            offeror_hands: vec![1, 2, 3],
+            offeror_draw_point: -1,
+            expires: None,
+            escrow: Some(true),
+        };
+        let offer_id = assigned_id(&handle(&mut deps, env, msg).unwrap());
+        deps.querier.set_owner("nft_id_1", contract_addr);
+
+        // the escrowed nft is requested back by default, then refunded to the offeror
+        let msg = HandleMsg::CounterOffer {
+            id: offer_id,
+            offeror_nfts: None,
+            offeree_nfts: None,
+            offeree_funds: None,
+            offeror_hands: vec![2, 2, 2],
+            offeror_draw_point: 0,
+            expires: None,
+            escrow: None,
+        };
+        let res = handle(&mut deps, mock_env("nft_owner_2", &[]), msg).unwrap();
+        let counter_id = assigned_id(&res);
+        let expected =
+            transfer_nft_msg(&nft("offeror_contract", "nft_id_1"), "nft_owner_1".into()).unwrap();
+        assert_eq!(vec![expected], res.messages);
+
+        let counter_offer = offers_read(&deps.storage)
+            .load(&counter_id.to_be_bytes())
+            .unwrap();
+        assert_eq!(
+            vec![nft("offeror_contract", "nft_id_1")],
+            counter_offer.offeree_nfts
+        );
+
+        // the nfts not held for the countered offer are still checked
+        let msg = HandleMsg::CounterOffer {
+            id: counter_id,
+            offeror_nfts: None,
+            offeree_nfts: Some(vec![nft("offeree_contract", "nft_id_3")]),
+            offeree_funds: None,
+            offeror_hands: vec![2, 2, 2],
+            offeror_draw_point: 0,
+            expires: None,
+            escrow: None,
+        };
+        let res = handle(&mut deps, mock_env("nft_owner_1", &[]), msg);
+        assert_eq!(true, res.is_err());
+    }
+
+    #[test]
+    fn query_offer() {
+        let mut deps = initialize();
+
+        let env = mock_env("nft_owner_1", &[]);
+        let msg = valid_sample_offer_msg();
+        let res = handle(&mut deps, env.clone(), msg).unwrap();
+        let offer_id = assigned_id(&res);
+
+        let offeror_key = viewing_key(&mut deps, "nft_owner_1");
+        let offeree_key = viewing_key(&mut deps, "nft_owner_2");
+        let offer_query = |address: &str, key: &str| QueryMsg::Offer {
+            id: offer_id,
+            address: Some(address.into()),
+            viewing_key: Some(key.to_string()),
+        };
+        let public_query = QueryMsg::Offer {
+            id: offer_id,
+            address: None,
+            viewing_key: None,
+        };
+
+        // only the status is public
+        let res = query(&deps, public_query.clone()).unwrap();
+        let offer: PublicOfferResponse = from_binary(&res).unwrap();
+        let mut expected = PublicOfferResponse {
+            id: offer_id,
+            status: OfferStatus::Offered,
+            open: false,
+            expires: Expiration::Never,
+            terms: None,
+            offeror_draw_point: None,
+            offeror: None,
+            offeree: None,
+            winner: None,
+        };
+        assert_eq!(expected, offer);
+
+        // offeror hands shown to the offeror
+        let res = query(&deps, offer_query("nft_owner_1", &offeror_key)).unwrap();
+        let offer: Offer = from_binary(&res).unwrap();
+        let offeror_hands: Hands = vec![Hand::Rock, Hand::Paper, Hand::Scissors].into();
+        assert_eq!(offeror_hands, offer.offeror_hands);
+
+        // offeror hands hidden against the offeree
+        let res = query(&deps, offer_query("nft_owner_2", &offeree_key)).unwrap();
+        let offer: Offer = from_binary(&res).unwrap();
+        assert_eq!(Hands::from(Vec::<Hand>::new()), offer.offeror_hands);
+
+        // failed by non participant, wrong viewing key or missing viewing key
+        let other_key = viewing_key(&mut deps, "nft_owner_3");
+        let res = query(&deps, offer_query("nft_owner_3", &other_key));
+        assert_eq!(Some(StdError::unauthorized()), res.err());
+        let res = query(&deps, offer_query("nft_owner_1", &offeree_key));
+        assert_eq!(Some(StdError::unauthorized()), res.err());
+        let msg = QueryMsg::Offer {
+            id: offer_id,
+            address: Some("nft_owner_1".into()),
+            viewing_key: None,
+        };
+        assert_eq!(Some(StdError::unauthorized()), query(&deps, msg).err());
+
+        // failed by publishing before settled
+        let msg = HandleMsg::PublishOffer { id: offer_id };
+        let res = handle(&mut deps, env.clone(), msg.clone());
+        assert_eq!(
+            Some(StdError::generic_err(format!(
+                "offer({}) is not settled, status: Offered",
+                offer_id
+            ))),
+            res.err()
+        );
+
+        // both hands shown to the participants after accepted
+        let offeree_env = mock_env("nft_owner_2", &[]);
+        let accept_msg = HandleMsg::AcceptOffer {
+            id: offer_id,
+            offeree_hands: vec![2, 3, 3],
+            offeree_nft: None,
+        };
+        handle(&mut deps, offeree_env.clone(), accept_msg).unwrap();
+
+        let res = query(&deps, offer_query("nft_owner_2", &offeree_key)).unwrap();
+        let offer: Offer = from_binary(&res).unwrap();
+        assert_eq!(offeror_hands, offer.offeror_hands);
+        let offeree_hands: Hands = vec![Hand::Paper, Hand::Scissors, Hand::Scissors].into();
+        assert_eq!(offeree_hands, offer.offeree_hands);
+
+        // nothing shown but the status until published
+        let res = query(&deps, public_query.clone()).unwrap();
+        let public: PublicOfferResponse = from_binary(&res).unwrap();
+        expected.status = OfferStatus::Accepted;
+        assert_eq!(expected, public);
+
+        // failed by non participant
+        let res = handle(&mut deps, mock_env("nft_owner_3", &[]), msg.clone());
+        assert_eq!(
+            Some(StdError::generic_err(format!(
+                "msg sender is not a participant of offer({})",
+                offer_id
+            ))),
+            res.err()
+        );
+
+        // each participant publishes own side
+        handle(&mut deps, env, msg.clone()).unwrap();
+        let res = query(&deps, public_query.clone()).unwrap();
+        let public: PublicOfferResponse = from_binary(&res).unwrap();
+        expected.offeror_draw_point = Some(-1);
+        expected.offeror = Some(PublishedSide {
+            player: "nft_owner_1".into(),
+            nfts: offer.offeror_nfts.clone(),
+            funds: vec![],
+            hands: offeror_hands,
+        });
+        expected.winner = Some(offer.winner.clone());
+        assert_eq!(expected, public);
+
+        handle(&mut deps, offeree_env, msg).unwrap();
+        let res = query(&deps, public_query).unwrap();
+        let public: PublicOfferResponse = from_binary(&res).unwrap();
+        expected.offeree = Some(PublishedSide {
+            player: "nft_owner_2".into(),
+            nfts: offer.offeree_nfts,
+            funds: vec![],
+            hands: offeree_hands,
+        });
+        assert_eq!(expected, public);
+    }
+
+    #[test]
+    fn query_offers() {
+        let mut deps = initialize();
+
+        let env = mock_env("nft_owner_1", &[]);
+        let mut ids = vec![];
+        for _ in 0..3 {
+            let res = handle(&mut deps, env.clone(), valid_sample_offer_msg()).unwrap();
+            ids.push(assigned_id(&res));
+        }
+        let msg = HandleMsg::CancelOffer { id: ids[1] };
+        handle(&mut deps, env.clone(), msg).unwrap();
+
+        let mut keys = vec![];
+        for sender in &["nft_owner_1", "nft_owner_2"] {
+            let msg = HandleMsg::GenerateViewingKey {
+                entropy: "entropy".to_string(),
+                padding: None,
+            };
+            let res = handle(&mut deps, mock_env(*sender, &[]), msg).unwrap();
+            let key: String = from_binary(&res.data.unwrap()).unwrap();
+            keys.push(key);
+        }
+        let offers_msg =
+            |address: &str, key: &str, role, status, start_after, limit| QueryMsg::Offers {
+                address: address.into(),
+                viewing_key: key.to_string(),
+                role,
+                status,
+                start_after,
+                limit,
+            };
+
+        // offeror sees own offers with hands
+        let msg = offers_msg(
+            "nft_owner_1",
+            &keys[0],
+            OfferRole::Offeror,
+            None,
+            None,
+            None,
+        );
+        let res: OffersResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        let got: Vec<u64> = res.offers.iter().map(|o| o.id).collect();
+        assert_eq!(ids, got);
+        let expected: Hands = vec![Hand::Rock, Hand::Paper, Hand::Scissors].into();
+        assert_eq!(expected, res.offers[0].offeror_hands);
+
+        // offeree sees incoming offers without offeror hands
+        let msg = offers_msg(
+            "nft_owner_2",
+            &keys[1],
+            OfferRole::Offeree,
+            None,
+            None,
+            None,
+        );
+        let res: OffersResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(3, res.offers.len());
+        let expected: Hands = Vec::<Hand>::new().into();
+        assert_eq!(expected, res.offers[0].offeror_hands);
+
+        // filtered by status
+        let status = Some(OfferStatus::Offered);
+        let msg = offers_msg(
+            "nft_owner_2",
+            &keys[1],
+            OfferRole::Offeree,
+            status,
+            None,
+            None,
+        );
+        let res: OffersResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        let got: Vec<u64> = res.offers.iter().map(|o| o.id).collect();
+        assert_eq!(vec![ids[0], ids[2]], got);
+
+        // paginated
+        let msg = offers_msg(
+            "nft_owner_1",
+            &keys[0],
+            OfferRole::Offeror,
+            None,
+            None,
+            Some(2),
+        );
+        let res: OffersResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        let got: Vec<u64> = res.offers.iter().map(|o| o.id).collect();
+        assert_eq!(vec![ids[0], ids[1]], got);
+        let start_after = Some(ids[1]);
+        let msg = offers_msg(
+            "nft_owner_1",
+            &keys[0],
+            OfferRole::Offeror,
+            None,
+            start_after,
+            None,
+        );
+        let res: OffersResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        let got: Vec<u64> = res.offers.iter().map(|o| o.id).collect();
+        assert_eq!(vec![ids[2]], got);
+
+        // failed by the other's viewing key
+        let msg = offers_msg(
+            "nft_owner_1",
+            &keys[1],
+            OfferRole::Offeror,
+            None,
+            None,
+            None,
+        );
+        let res = query(&deps, msg);
+        assert_eq!(Some(StdError::unauthorized()), res.err());
+    }
+
+    #[test]
+    fn bet_token() {
+        let mut deps = initialize();
+        let denom = "uscrt".to_string();
+
+        let mut pass_win = false;
+        let mut pass_draw = false;
+        let mut pass_lose = false;
+        let mut id: u64 = 0;
+        while !pass_win || !pass_draw || !pass_lose {
+            id += 1;
+
+            let amount: u128 = 100;
+            let env = mock_env("bettor_1", &coins(amount, &denom));
+            let fee = calculate_fee(amount, DEFAULT_FEE_RATE);
+
+            let msg = HandleMsg::BetToken {
+                id: None,
+                hand: 1,
+                entropy: "entropy".to_string(),
+                commit: None,
+            };
+            let res = handle(&mut deps, env, msg).unwrap();
+            assert_eq!(id, assigned_id(&res));
+            assert_eq!(2, res.log.len());
+
+            let result = &res.log[1].value;
+            if result == "lose" {
+                assert_eq!(0, res.messages.len());
+                pass_lose = true;
+                continue;
+            }
+            let msg_amount = match &res.messages[0] {
+                CosmosMsg::Bank(BankMsg::Send { amount, .. }) => amount[0].amount.u128(),
+                _ => panic!("unexpected"),
+            };
+
+            if result == "win" {
+                pass_win = true;
+                assert_eq!(msg_amount, amount * 2 - fee);
+            } else {
+                pass_draw = true;
+                assert_eq!(msg_amount, amount - fee);
+            }
+        }
+    }
+
+    #[test]
+    fn withdraw_fee() {
+        let mut deps = initialize();
+        let denom = "uscrt".to_string();
+        let amount: u128 = 100;
+        let fee = calculate_fee(amount, DEFAULT_FEE_RATE);
+        let lp_fee = calculate_fee(fee, DEFAULT_LP_FEE_SHARE);
+        let accrued_fee = fee - lp_fee;
+
+        let env = mock_env("bettor_1", &coins(amount, &denom));
+        let msg = HandleMsg::BetToken {
+            id: None,
+            hand: 1,
+            entropy: "entropy".to_string(),
+            commit: None,
+        };
+        let res = handle(&mut deps, env, msg).unwrap();
+
+        // the fee is accrued apart from the reserve, except the cut for the liquidity providers
+        let msg = QueryMsg::FeeLedger {
+            denom: denom.clone(),
+        };
+        let ledger: FeeLedgerResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(Uint128(accrued_fee), ledger.accrued_fee);
+        let reserve = match res.log[1].value.as_str() {
+            "win" => 10000 - amount + lp_fee,
+            "draw" => 10000 + lp_fee,
+            _ => 10000 + amount - fee + lp_fee,
+        };
+        assert_eq!(Uint128(reserve), ledger.reserve);
+
+        // failed by non fee recipient
+        let env = mock_env("bettor_1", &[]);
+        let msg = HandleMsg::WithdrawFee {
+            denom: denom.clone(),
+            amount: Uint128(accrued_fee),
+        };
+        let res = handle(&mut deps, env, msg);
+        assert_eq!(Some(StdError::unauthorized()), res.err());
+
+        // failed by withdrawing more than accrued
+        let env = mock_env("deployer", &[]);
+        let msg = HandleMsg::WithdrawFee {
+            denom: denom.clone(),
+            amount: Uint128(accrued_fee + 1),
+        };
+        let res = handle(&mut deps, env, msg);
+        assert_eq!(
+            Some(StdError::generic_err(format!(
+                "insufficient accrued fee of {}({})",
+                denom, accrued_fee
+            ))),
+            res.err()
+        );
+
+        // failed by withdrawing nothing
+        let env = mock_env("deployer", &[]);
+        let msg = HandleMsg::WithdrawFee {
+            denom: denom.clone(),
+            amount: Uint128(0),
+        };
+        let res = handle(&mut deps, env, msg);
+        assert_eq!(
+            Some(StdError::generic_err(
+                "withdrawal amount should not be zero"
+            )),
+            res.err()
+        );
 
         // succeed
-        handle(&mut deps, env.clone(), msg.clone()).unwrap();
+        let env = mock_env("deployer", &[]);
+        let msg = HandleMsg::WithdrawFee {
+            denom: denom.clone(),
+            amount: Uint128(accrued_fee),
+        };
+        let res = handle(&mut deps, env, msg).unwrap();
+        assert_eq!(
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                from_address: MOCK_CONTRACT_ADDR.into(),
+                to_address: "deployer".into(),
+                amount: coins(accrued_fee, &denom),
+            })],
+            res.messages
+        );
+        let msg = QueryMsg::FeeLedger { denom };
+        let ledger: FeeLedgerResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(Uint128(0), ledger.accrued_fee);
+        assert_eq!(Uint128(reserve), ledger.reserve);
+    }
+
+    #[test]
+    fn deposit_and_withdraw() {
+        let mut deps = initialize();
+        let denom = "uscrt".to_string();
+
+        // the shares are minted at the current share price
+        let env = mock_env("bettor_1", &coins(500, &denom));
+        let res = handle(&mut deps, env, HandleMsg::Deposit {}).unwrap();
+        assert_eq!(log("shares", 500), res.log[1]);
+        reserves(&mut deps.storage)
+            .save(denom.as_bytes(), &Uint128(21000))
+            .unwrap();
+        let env = mock_env("bettor_2", &coins(1000, &denom));
+        let res = handle(&mut deps, env, HandleMsg::Deposit {}).unwrap();
+        assert_eq!(log("shares", 500), res.log[1]);
+
+        // failed by too small deposit
+        let env = mock_env("bettor_2", &coins(1, &denom));
+        let res = handle(&mut deps, env, HandleMsg::Deposit {});
+        assert_eq!(
+            Some(StdError::generic_err(
+                "deposit(1uscrt) is too small to mint a share"
+            )),
+            res.err()
+        );
+
+        // the shares are queried with viewing key
+        let env = mock_env("bettor_2", &[]);
+        let msg = HandleMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+            padding: None,
+        };
+        let res = handle(&mut deps, env, msg).unwrap();
+        let viewing_key: String = from_binary(&res.data.unwrap()).unwrap();
+        let shares_query = |viewing_key: &str| QueryMsg::Shares {
+            address: "bettor_2".into(),
+            viewing_key: viewing_key.to_string(),
+            denom: denom.clone(),
+        };
+        let res = query(&deps, shares_query("wrong_key"));
+        assert_eq!(Some(StdError::unauthorized()), res.err());
+        let res = query(&deps, shares_query(&viewing_key)).unwrap();
+        let expected = SharesResponse {
+            denom: denom.clone(),
+            shares: Uint128(500),
+            total_shares: Uint128(11000),
+            amount: Uint128(1000),
+        };
+        assert_eq!(expected, from_binary(&res).unwrap());
+
+        // failed by withdrawing more shares than owned
+        let env = mock_env("bettor_2", &[]);
+        let msg = HandleMsg::Withdraw {
+            denom: denom.clone(),
+            shares: Uint128(501),
+        };
+        let res = handle(&mut deps, env, msg);
+        assert_eq!(
+            Some(StdError::generic_err("insufficient shares of uscrt(500)")),
+            res.err()
+        );
+
+        // succeed even if the contract is stopped
+        let msg = HandleMsg::SetContractStatus {
+            level: ContractStatus::StopAll,
+        };
+        handle(&mut deps, mock_env("deployer", &[]), msg).unwrap();
+        let env = mock_env("bettor_2", &[]);
+        let msg = HandleMsg::Withdraw {
+            denom: denom.clone(),
+            shares: Uint128(500),
+        };
+        let res = handle(&mut deps, env, msg).unwrap();
+        assert_eq!(
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                from_address: MOCK_CONTRACT_ADDR.into(),
+                to_address: "bettor_2".into(),
+                amount: coins(1000, &denom),
+            })],
+            res.messages
+        );
+        let msg = QueryMsg::FeeLedger { denom };
+        let ledger: FeeLedgerResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(Uint128(21000), ledger.reserve);
+    }
+
+    #[test]
+    fn settle_bet() {
+        let mut deps = initialize();
+        let denom = "uscrt".to_string();
+        let amount: u128 = 100;
+        let fee = calculate_fee(amount, DEFAULT_FEE_RATE);
+        let lp_fee = calculate_fee(fee, DEFAULT_LP_FEE_SHARE);
+        let reserve = |deps: &Extern<MockStorage, MockApi, MockQuerier>| {
+            let msg = QueryMsg::FeeLedger {
+                denom: "uscrt".to_string(),
+            };
+            let ledger: FeeLedgerResponse = from_binary(&query(deps, msg).unwrap()).unwrap();
+            ledger.reserve.u128()
+        };
+        let bet_msg = HandleMsg::BetToken {
+            id: None,
+            hand: 1,
+            entropy: "entropy".to_string(),
+            commit: Some(true),
+        };
+
+        // the bet is only recorded, and the reserve is held for it
+        let env = mock_env("bettor_1", &coins(amount, &denom));
+        let res = handle(&mut deps, env.clone(), bet_msg.clone()).unwrap();
+        let id = assigned_id(&res);
+        assert_eq!(0, res.messages.len());
+        assert_eq!(log("result", "pending"), res.log[1]);
+        assert_eq!(10000 - amount, reserve(&deps));
+        let pending = pending_bets_read(&deps.storage)
+            .load(&id.to_be_bytes())
+            .unwrap();
+        assert_eq!(
+            deps.api.canonical_address(&"bettor_1".into()).unwrap(),
+            pending.bettor
+        );
+        assert_eq!(1, pending.epoch);
+        assert_ne!(Binary(vec![]), pending.entropy);
+
+        // listed in the history of the bettor as pending
+        let msg = QueryMsg::MyTokenBets {
+            address: "bettor_1".into(),
+            viewing_key: viewing_key(&mut deps, "bettor_1"),
+            start_after: None,
+            limit: None,
+        };
+        let res: TokenBetsResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(1, res.token_bets.len());
+        assert_eq!(id, res.token_bets[0].id);
+        assert_eq!("pending", res.token_bets[0].result);
+
+        // the held reserve is priced into the shares
+        let deposit_env = mock_env("provider", &coins(10000, &denom));
+        let res = handle(&mut deps, deposit_env, HandleMsg::Deposit {}).unwrap();
+        assert_eq!(log("shares", 10000), res.log[1]);
+        let withdraw_msg = HandleMsg::Withdraw {
+            denom: denom.clone(),
+            shares: Uint128(10000),
+        };
+        let res = handle(&mut deps, mock_env("deployer", &[]), withdraw_msg.clone()).unwrap();
+        assert_eq!(log("amount", 10000), res.log[1]);
+
+        // failed by withdrawing the reserve held for the bet
+        let res = handle(&mut deps, mock_env("provider", &[]), withdraw_msg);
+        assert_eq!(
+            Some(StdError::generic_err(
+                "insufficient reserve of uscrt(9900), the rest is held for the pending bets"
+            )),
+            res.err()
+        );
+
+        // failed by settling in the same block
+        let settle_msg = HandleMsg::SettleBet { id };
+        let res = handle(&mut deps, mock_env("anyone", &[]), settle_msg.clone());
+        assert_eq!(
+            Some(StdError::generic_err(format!(
+                "bet({}) can't be settled until the next block",
+                id
+            ))),
+            res.err()
+        );
+
+        // succeed by anyone at any later block, with the result fixed at the bet
+        let mut settle_env = mock_env("anyone", &[]);
+        settle_env.block.height += 1000;
+        let res = handle(&mut deps, settle_env.clone(), settle_msg.clone()).unwrap();
+        let seed = epoch_seed(&deps.storage, pending.epoch).unwrap();
+        let rng = Prng::new_rand_bytes(&seed, pending.entropy.as_slice());
+        let result = pending.hand.compete(&rand_hand(&rng)).to_string();
+        assert_eq!(log("result", &result), res.log[1]);
+        let expected = match result.as_str() {
+            "win" => 10000 - amount + lp_fee,
+            "draw" => 10000 + lp_fee,
+            _ => 10000 + amount - fee + lp_fee,
+        };
+        assert_eq!(expected, reserve(&deps));
+        let msg = QueryMsg::TokenBet {
+            id,
+            address: "bettor_1".into(),
+            viewing_key: viewing_key(&mut deps, "bettor_1"),
+        };
+        let bet: TokenBet = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(result, bet.result);
+
+        // failed by settling again
+        let res = handle(&mut deps, settle_env, settle_msg);
+        assert_eq!(
+            Some(StdError::generic_err(format!(
+                "pending bet({}) is not found",
+                id
+            ))),
+            res.err()
+        );
+
+        // only refunded while the contract is stopped
+        let res = handle(&mut deps, env, bet_msg).unwrap();
+        let id = assigned_id(&res);
+        let status_msg = HandleMsg::SetContractStatus {
+            level: ContractStatus::StopAll,
+        };
+        handle(&mut deps, mock_env("deployer", &[]), status_msg).unwrap();
+        let mut settle_env = mock_env("anyone", &[]);
+        settle_env.block.height += 1;
+        let res = handle(&mut deps, settle_env, HandleMsg::SettleBet { id }).unwrap();
+        assert_eq!(log("result", "refunded"), res.log[1]);
+        assert_eq!(
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                from_address: MOCK_CONTRACT_ADDR.into(),
+                to_address: "bettor_1".into(),
+                amount: coins(amount, &denom),
+            })],
+            res.messages
+        );
+        assert_eq!(expected, reserve(&deps));
+    }
+
+    #[test]
+    fn rotate_seed() {
+        let mut deps = initialize();
+
+        // the house seed is committed at init
+        let prng_seed = config_read(&deps.storage).load().unwrap().prng_seed;
+        let msg = QueryMsg::Epoch { id: None };
+        let epoch: Epoch = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(1, epoch.id);
+        assert_eq!(
+            Binary(sha_256(&house_seed(&prng_seed)).to_vec()),
+            epoch.commitment
+        );
+        assert_eq!(None, epoch.seed);
+
+        let env = mock_env("bettor_1", &coins(100, "uscrt"));
+        let msg = HandleMsg::BetToken {
+            id: None,
+            hand: 1,
+            entropy: "entropy".to_string(),
+            commit: None,
+        };
+        let id = assigned_id(&handle(&mut deps, env, msg).unwrap());
+
+        // the entropy is hidden until the seed is revealed
+        let key = viewing_key(&mut deps, "bettor_1");
+        let msg = QueryMsg::TokenBet {
+            id,
+            address: "bettor_1".into(),
+            viewing_key: key.clone(),
+        };
+        let bet: TokenBet = from_binary(&query(&deps, msg.clone()).unwrap()).unwrap();
+        assert_eq!(1, bet.epoch);
+        assert_eq!(Binary(vec![]), bet.entropy);
+        let msg = QueryMsg::MyTokenBets {
+            address: "bettor_1".into(),
+            viewing_key: key.clone(),
+            start_after: None,
+            limit: None,
+        };
+        let res: TokenBetsResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(vec![bet], res.token_bets);
+
+        // failed by non admin
+        let msg = HandleMsg::RotateSeed {
+            seed: "next_seed".to_string(),
+        };
+        let res = handle(&mut deps, mock_env("bettor_1", &[]), msg.clone());
+        assert_eq!(Some(StdError::unauthorized()), res.err());
+
+        // succeed by admin, revealing the seed of the last epoch
+        let entropy = config_read(&deps.storage).load().unwrap().entropy;
+        let res = handle(&mut deps, mock_env("deployer", &[]), msg).unwrap();
+        assert_eq!(2, assigned_id(&res));
+        let msg = QueryMsg::Epoch { id: Some(1) };
+        let epoch: Epoch = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        let seed = epoch.seed.unwrap();
+        assert_eq!(Binary(house_seed(&prng_seed).to_vec()), seed);
+        // neither the prng seed nor the one of init is revealed
+        assert_ne!(Binary(prng_seed), seed);
+        assert_ne!(Binary(b"prng_seed".to_vec()), seed);
+
+        // the bet is recomputed from the revealed seed and its entropy
+        let msg = QueryMsg::TokenBet {
+            id,
+            address: "bettor_1".into(),
+            viewing_key: key,
+        };
+        let bet: TokenBet = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(1, bet.epoch);
+        assert_ne!(Binary(vec![]), bet.entropy);
+        // which is not the entropy of the contract
+        assert_ne!(Binary(entropy.clone()), bet.entropy);
+        let rng = Prng::new_rand_bytes(seed.as_slice(), bet.entropy.as_slice());
+        assert_eq!(bet.hand.compete(&rand_hand(&rng)).to_string(), bet.result);
+
+        let msg = QueryMsg::Epoch { id: None };
+        let epoch: Epoch = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(2, epoch.id);
+        // the seed of the admin is mixed with the entropy of the contract
+        let next_seed = sha_256(&[b"next_seed", entropy.as_slice()].concat());
+        assert_eq!(Binary(sha_256(&next_seed).to_vec()), epoch.commitment);
+        assert_ne!(Binary(sha_256(b"next_seed").to_vec()), epoch.commitment);
+        assert_eq!(None, epoch.seed);
+
+        let msg = QueryMsg::Epoch { id: Some(3) };
+        assert_eq!(
+            Some(StdError::generic_err("epoch(3) is not found")),
+            query(&deps, msg).err()
+        );
+    }
+
+    #[test]
+    fn bet_token_invalid_hand() {
+        let mut deps = initialize();
+
+        let env = mock_env("bettor_1", &coins(100, "uscrt"));
+        let msg = HandleMsg::BetToken {
+            id: None,
+            hand: 0,
+            entropy: "entropy".to_string(),
+            commit: None,
+        };
+        let res = handle(&mut deps, env, msg);
+        assert_eq!(
+            Some(StdError::generic_err(
+                "invalid hand(0), should be 1(Rock), 2(Paper) or 3(Scissors)"
+            )),
+            res.err()
+        );
+    }
 
-        // failed by duplicated id
-        let res = handle(&mut deps, env.clone(), msg);
+    #[test]
+    fn bet_limits() {
+        let mut deps = initialize();
+        let denom = "uscrt".to_string();
+
+        // the default limits
+        let msg = QueryMsg::BetLimits {
+            denom: denom.clone(),
+        };
+        let res: BetLimitsResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        let expected = BetLimitsResponse {
+            denom: denom.clone(),
+            min_bet: Uint128(1),
+            max_bet: None,
+            max_payout_rate: DEFAULT_MAX_PAYOUT_RATE,
+            max_payout: Uint128(1000),
+        };
+        assert_eq!(expected, res);
+
+        let msg = HandleMsg::SetBetLimits {
+            denom: denom.clone(),
+            min_bet: Uint128(10),
+            max_bet: Some(Uint128(200)),
+            max_payout_rate: 300,
+        };
+
+        // failed by non admin
+        let res = handle(&mut deps, mock_env("anyone", &[]), msg.clone());
+        assert_eq!(Some(StdError::unauthorized()), res.err());
+
+        // failed by min bet more than max bet
+        let invalid_msg = HandleMsg::SetBetLimits {
+            denom: denom.clone(),
+            min_bet: Uint128(300),
+            max_bet: Some(Uint128(200)),
+            max_payout_rate: 300,
+        };
+        let res = handle(&mut deps, mock_env("deployer", &[]), invalid_msg);
         assert_eq!(
-            Some(StdError::generic_err(format!(
-                "duplicated id({})",
-                offer_id
-            ))),
+            Some(StdError::generic_err(
+                "min_bet(300) should not be more than max_bet(200)"
+            )),
             res.err()
         );
 
-        // failed by invalid nft_id
-        let msg = HandleMsg::MakeOffer {
-            id: offer_id + 1,
-            offeree: "nft_owner_2".into(),
-            offeror_nft_contract: "contract".into(),
-            offeror_nft: "invalid_nft_id".to_string(),
-            offeror_code_hash: "code_hash".to_string(),
-            offeree_nft_contract: "contract".into(),
-            offeree_nft: "nft_id_2".to_string(),
-            offeree_code_hash: "code_hash".to_string(),
-            offeror_hands: vec![1, 2, 3],
-            offeror_draw_point: 2,
+        // succeed
+        handle(&mut deps, mock_env("deployer", &[]), msg).unwrap();
+        let msg = QueryMsg::BetLimits {
+            denom: denom.clone(),
         };
+        let res: BetLimitsResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(Uint128(300), res.max_payout);
 
+        let bet = |amount: u128| {
+            (
+                mock_env("bettor_1", &coins(amount, &denom)),
+                HandleMsg::BetToken {
+                    id: None,
+                    hand: 1,
+                    entropy: "entropy".to_string(),
+                    commit: None,
+                },
+            )
+        };
+
+        // failed by less than min bet
+        let (env, msg) = bet(5);
         let res = handle(&mut deps, env, msg);
-        assert_eq!(true, res.is_err());
+        assert_eq!(
+            Some(StdError::generic_err("bet(5) is less than the min bet(10)")),
+            res.err()
+        );
+
+        // failed by more than max bet
+        let (env, msg) = bet(201);
+        let res = handle(&mut deps, env, msg);
+        assert_eq!(
+            Some(StdError::generic_err(
+                "bet(201) is more than the max bet(200)"
+            )),
+            res.err()
+        );
+
+        // failed by more than max payout
+        let (env, msg) = bet(200);
+        let res = handle(&mut deps, env, msg);
+        assert_eq!(
+            Some(StdError::generic_err(
+                "payout(394) is more than the max payout(300)"
+            )),
+            res.err()
+        );
+
+        // succeed
+        let (env, msg) = bet(100);
+        handle(&mut deps, env, msg).unwrap();
     }
 
     #[test]
-    fn try_accept() {
+    fn bet_snip20_token() {
         let mut deps = initialize();
+        let token = "token_contract".to_string();
 
-        let offer_id = 100;
-        let env = mock_env("nft_owner_1", &[]);
-        let msg = valid_sample_offer_msg(offer_id);
-        handle(&mut deps, env, msg).unwrap();
+        // failed by non admin
+        let msg = HandleMsg::RegisterToken {
+            token_contract: token.clone().into(),
+            code_hash: "token_code_hash".to_string(),
+        };
+        let res = handle(&mut deps, mock_env("anyone", &[]), msg.clone());
+        assert_eq!(Some(StdError::unauthorized()), res.err());
 
-        let msg = HandleMsg::AcceptOffer {
-            id: offer_id,
-            offeree_hands: vec![3, 2, 1],
+        // succeed
+        let res = handle(&mut deps, mock_env("deployer", &[]), msg).unwrap();
+        assert_eq!(1, res.messages.len());
+
+        let receive_msg = |from: &str, amount: u128, msg: ReceiveTokenMsg| HandleMsg::Receive {
+            sender: from.into(),
+            from: from.into(),
+            amount: Uint128(amount),
+            msg: Some(to_binary(&msg).unwrap()),
+        };
+        let bet_msg = ReceiveTokenMsg::BetToken {
+            id: None,
+            hand: 1,
+            entropy: "entropy".to_string(),
+            commit: None,
         };
 
-        // faild by invalid sender
-        let env = mock_env("invalid_sender", &[]);
-        let res = handle(&mut deps, env, msg.clone());
+        // failed by unregistered token
+        let msg = receive_msg("bettor_1", 100, bet_msg.clone());
+        let res = handle(&mut deps, mock_env("other_contract", &[]), msg);
         assert_eq!(
             Some(StdError::generic_err(
-                "msg sender is not offeree(nft_owner_2)"
+                "unregistered token contract(other_contract)"
             )),
             res.err()
         );
 
-        // succeed
-        let env = mock_env("nft_owner_2", &[]);
-        let res = handle(&mut deps, env, msg).unwrap();
-        assert_eq!(1, res.messages.len());
+        // the reserve of the token is deposited by sending it
+        let msg = receive_msg("deployer", 10000, ReceiveTokenMsg::Deposit {});
+        handle(&mut deps, mock_env(token.as_str(), &[]), msg).unwrap();
 
-        let transfer_msg = to_binary(&Cw721HandleMsg::TransferNft {
-            recipient: "nft_owner_1".into(),
-            token_id: "nft_id_2".to_string(),
-            memo: None,
-            padding: None,
-        })
-        .unwrap();
-        let msg: CosmosMsg = WasmMsg::Execute {
-            contract_addr: "offeree_contract".into(),
-            callback_code_hash: "offeree_code_hash".to_string(),
-            msg: transfer_msg,
-            send: vec![],
+        // the payout is transferred by the token contract
+        let mut pass_win = false;
+        while !pass_win {
+            let msg = receive_msg("bettor_1", 100, bet_msg.clone());
+            let res = handle(&mut deps, mock_env(token.as_str(), &[]), msg).unwrap();
+            if res.log[1].value != "win" {
+                continue;
+            }
+            pass_win = true;
+            let fee = calculate_fee(100, DEFAULT_FEE_RATE);
+            let transfer = Snip20HandleMsg::Transfer {
+                recipient: "bettor_1".into(),
+                amount: Uint128(200 - fee),
+                padding: None,
+            };
+            let expected: CosmosMsg = WasmMsg::Execute {
+                contract_addr: token.clone().into(),
+                callback_code_hash: "token_code_hash".to_string(),
+                msg: to_binary(&transfer).unwrap(),
+                send: vec![],
+            }
+            .into();
+            assert_eq!(vec![expected], res.messages);
         }
-        .into();
-        assert_eq!(msg, res.messages[0]);
+
+        // the amount of a token with 18 decimals is beyond u64, but never truncated
+        let amount = 1_000_000 * 10u128.pow(18);
+        let msg = receive_msg("deployer", 100 * amount, ReceiveTokenMsg::Deposit {});
+        handle(&mut deps, mock_env(token.as_str(), &[]), msg).unwrap();
+        let fee = calculate_fee(amount, DEFAULT_FEE_RATE);
+        assert_eq!(
+            amount * DEFAULT_FEE_RATE as u128 / INVERSE_BASIS_POINT as u128,
+            fee
+        );
+        let mut pass_win = false;
+        while !pass_win {
+            let msg = receive_msg("bettor_1", amount, bet_msg.clone());
+            let res = handle(&mut deps, mock_env(token.as_str(), &[]), msg).unwrap();
+            if res.log[1].value != "win" {
+                continue;
+            }
+            pass_win = true;
+            let transfer = Snip20HandleMsg::Transfer {
+                recipient: "bettor_1".into(),
+                amount: Uint128(amount * 2 - fee),
+                padding: None,
+            };
+            let expected: CosmosMsg = WasmMsg::Execute {
+                contract_addr: token.clone().into(),
+                callback_code_hash: "token_code_hash".to_string(),
+                msg: to_binary(&transfer).unwrap(),
+                send: vec![],
+            }
+            .into();
+            assert_eq!(vec![expected], res.messages);
+
+            let msg = QueryMsg::TokenBet {
+                id: assigned_id(&res),
+                address: "bettor_1".into(),
+                viewing_key: viewing_key(&mut deps, "bettor_1"),
+            };
+            let bet: TokenBet = from_binary(&query(&deps, msg).unwrap()).unwrap();
+            assert_eq!(Uint128(amount), bet.amount);
+        }
+
+        // the fee is accounted per token contract
+        let msg = QueryMsg::FeeLedger { denom: token };
+        let ledger: FeeLedgerResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_ne!(Uint128(0), ledger.accrued_fee);
+        let msg = QueryMsg::FeeLedger {
+            denom: "uscrt".to_string(),
+        };
+        let ledger: FeeLedgerResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(Uint128(0), ledger.accrued_fee);
     }
 
     #[test]
-    fn query_offer() {
+    fn query_token_bet() {
         let mut deps = initialize();
 
-        let offer_id = 100;
-        let env = mock_env("nft_owner_1", &[]);
-        let msg = valid_sample_offer_msg(offer_id);
-        handle(&mut deps, env.clone(), msg).unwrap();
+        let denom = "uscrt".to_string();
+        let amount = 100;
+        let env = mock_env("bettor_1", &coins(amount, &denom));
+        let msg = HandleMsg::BetToken {
+            id: Some(123),
+            hand: 1,
+            entropy: "entropy".to_string(),
+            commit: None,
+        };
+        let res = handle(&mut deps, env.clone(), msg.clone()).unwrap();
+        let id = assigned_id(&res);
 
-        // offeror hands hidden
-        let msg = QueryMsg::Offer {
-            id: offer_id,
-            address: None,
-            viewing_key: None,
+        // failed by duplicated client id
+        let res = handle(&mut deps, env, msg);
+        assert_eq!(
+            Some(StdError::generic_err(format!(
+                "duplicated id(123), already assigned to {}",
+                id
+            ))),
+            res.err()
+        );
+
+        let key = viewing_key(&mut deps, "bettor_1");
+        let msg = QueryMsg::TokenBet {
+            id,
+            address: "bettor_1".into(),
+            viewing_key: key,
         };
         let res = query(&deps, msg).unwrap();
-        let offer: Offer = from_binary(&res).unwrap();
-        let expected: Hands = Vec::<Hand>::new().into();
-        assert_eq!(expected, offer.offeror_hands);
+        let bet: TokenBet = from_binary(&res).unwrap();
+        assert_eq!(
+            deps.api.canonical_address(&"bettor_1".into()).unwrap(),
+            bet.bettor
+        );
+        assert_eq!(denom, bet.denom);
+        assert_eq!(Uint128(amount), bet.amount);
+        assert_eq!(Hand::Rock, bet.hand);
 
-        // offeror hands shown
-        let msg = HandleMsg::GenerateViewingKey {
-            entropy: "entropy".to_string(),
-            padding: None,
+        // failed by wrong viewing key
+        let msg = QueryMsg::TokenBet {
+            id,
+            address: "bettor_1".into(),
+            viewing_key: "wrong_key".to_string(),
         };
-        let res = handle(&mut deps, env.clone(), msg).unwrap();
-        let data = res.data.unwrap();
-        let key: String = from_binary(&data).unwrap();
+        assert_eq!(Some(StdError::unauthorized()), query(&deps, msg).err());
 
-        let msg = QueryMsg::Offer {
-            id: offer_id,
-            address: Some(env.message.sender),
-            viewing_key: Some(key),
-        };
-        let res = query(&deps, msg).unwrap();
-        let offer: Offer = from_binary(&res).unwrap();
-        let expected: Hands = vec![Hand::Rock, Hand::Paper, Hand::Scissors].into();
-        assert_eq!(expected, offer.offeror_hands);
+        // failed by another bettor, same as a missing bet
+        let other_key = viewing_key(&mut deps, "bettor_2");
+        for id in &[id, id + 1] {
+            let msg = QueryMsg::TokenBet {
+                id: *id,
+                address: "bettor_2".into(),
+                viewing_key: other_key.clone(),
+            };
+            assert_eq!(Some(StdError::unauthorized()), query(&deps, msg).err());
+        }
+    }
 
-        // both hands shown
-        let env = mock_env("nft_owner_2", &[]);
-        let msg = HandleMsg::AcceptOffer {
-            id: offer_id,
-            offeree_hands: vec![2, 3, 3],
+    #[test]
+    fn set_viewing_key() {
+        let mut deps = initialize();
+
+        let env = mock_env("bettor_1", &[]);
+        let my_token_bets = |address: &str, key: &str| QueryMsg::MyTokenBets {
+            address: address.into(),
+            viewing_key: key.to_string(),
+            start_after: None,
+            limit: None,
         };
-        handle(&mut deps, env, msg).unwrap();
 
+        // failed without any key, by invalid address, and for the offer as well
+        let res = query(&deps, my_token_bets("bettor_1", "my_key"));
+        assert_eq!(Some(StdError::unauthorized()), res.err());
+        let res = query(&deps, my_token_bets("b", "my_key"));
+        assert_eq!(Some(StdError::unauthorized()), res.err());
         let msg = QueryMsg::Offer {
-            id: offer_id,
-            address: None,
-            viewing_key: None,
+            id: 1,
+            address: Some("bettor_1".into()),
+            viewing_key: Some("my_key".to_string()),
         };
-        let res = query(&deps, msg).unwrap();
-        let offer: Offer = from_binary(&res).unwrap();
-        let offeror_expected: Hands = vec![Hand::Rock, Hand::Paper, Hand::Scissors].into();
-        assert_eq!(offeror_expected, offer.offeror_hands);
-        let offeree_expected: Hands = vec![Hand::Paper, Hand::Scissors, Hand::Scissors].into();
-        assert_eq!(offeree_expected, offer.offeree_hands);
-    }
-
-    // #[test]
-    // fn query_offers() {
-    //     let mut deps = initialize();
-    //     let id_1 = 100;
-    //     let msg = valid_sample_offer_msg(id_1);
-    //     handle(&mut deps, mock_env("nft_owner_1", &[]), msg).unwrap();
-    //     let id_2 = 101;
-    //     let msg = valid_sample_offer_msg(id_2);
-    //     handle(&mut deps, mock_env("nft_owner_1", &[]), msg).unwrap();
-
-    //     let msg = QueryMsg::Offers {};
-    //     let res = query(&deps, msg).unwrap();
-    //     let offers: OffersResponse = from_binary(&res).unwrap();
-    //     assert_eq!(vec![id_1, id_2], offers.ids)
-    // }
+        assert_eq!(Some(StdError::unauthorized()), query(&deps, msg).err());
+
+        // succeed by the key set
+        let msg = HandleMsg::SetViewingKey {
+            key: "my_key".to_string(),
+            padding: None,
+        };
+        handle(&mut deps, env.clone(), msg).unwrap();
+        assert!(query(&deps, my_token_bets("bettor_1", "my_key")).is_ok());
+        let res = query(&deps, my_token_bets("bettor_2", "my_key"));
+        assert_eq!(Some(StdError::unauthorized()), res.err());
+
+        // the old key is replaced by the generated one
+        let generated_key = viewing_key(&mut deps, "bettor_1");
+        let res = query(&deps, my_token_bets("bettor_1", "my_key"));
+        assert_eq!(Some(StdError::unauthorized()), res.err());
+        assert!(query(&deps, my_token_bets("bettor_1", &generated_key)).is_ok());
+
+        // failed after revoked
+        let msg = HandleMsg::RevokeViewingKey { padding: None };
+        handle(&mut deps, env, msg).unwrap();
+        let res = query(&deps, my_token_bets("bettor_1", &generated_key));
+        assert_eq!(Some(StdError::unauthorized()), res.err());
+    }
 
     #[test]
-    fn bet_token() {
+    fn query_with_permit() {
         let mut deps = initialize();
-        let denom = "uscrt".to_string();
 
-        let mut pass_win = false;
-        let mut pass_draw = false;
-        let mut pass_lose = false;
-        let mut id: u64 = 0;
-        while !pass_win || !pass_draw || !pass_lose {
-            id += 1;
+        let secret_key = [1u8; 32];
+        let params = |permissions: Vec<Permission>| PermitParams {
+            allowed_tokens: vec![MOCK_CONTRACT_ADDR.into()],
+            permit_name: "janken".to_string(),
+            chain_id: "secret-4".to_string(),
+            permissions,
+        };
+        let permit = mock_permit(&secret_key, params(vec![Permission::TokenBets]));
+        let signer = pub_key_to_address(permit.signature.pub_key.value.as_slice()).unwrap();
+        let with_permit = |permit: &Permit, query| QueryMsg::WithPermit {
+            permit: permit.clone(),
+            query,
+        };
 
-            let amount: u64 = 100;
-            let env = mock_env("bettor_1", &coins(amount.into(), &denom));
-            let fee = calculate_fee(amount, DEFAULT_FEE_RATE);
+        let env = mock_env(signer.as_str(), &coins(100, "uscrt"));
+        let msg = HandleMsg::BetToken {
+            id: None,
+            hand: 1,
+            entropy: "entropy".to_string(),
+            commit: None,
+        };
+        let id = assigned_id(&handle(&mut deps, env, msg).unwrap());
 
-            let msg = HandleMsg::BetToken {
-                id,
-                hand: 1,
-                entropy: "entropy".to_string(),
-            };
-            let res = handle(&mut deps, env, msg).unwrap();
-            assert_eq!(2, res.log.len());
+        // the bets of the signer are queried without viewing key
+        let msg = with_permit(
+            &permit,
+            QueryWithPermit::MyTokenBets {
+                start_after: None,
+                limit: None,
+            },
+        );
+        let res: TokenBetsResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(1, res.token_bets.len());
+        assert_eq!(id, res.token_bets[0].id);
+        let msg = with_permit(&permit, QueryWithPermit::TokenBet { id });
+        let bet: TokenBet = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(deps.api.canonical_address(&signer).unwrap(), bet.bettor);
 
-            let result = &res.log[1].value;
-            if result == "lose" {
-                assert_eq!(0, res.messages.len());
-                pass_lose = true;
-                continue;
-            }
-            let msg_amount = match &res.messages[0] {
-                CosmosMsg::Bank(BankMsg::Send { amount, .. }) => amount[0].amount.u128() as u64,
-                _ => panic!("unexpected"),
-            };
+        // failed by the permission not granted
+        let shares_query = || QueryWithPermit::Shares {
+            denom: "uscrt".to_string(),
+        };
+        let res = query(&deps, with_permit(&permit, shares_query()));
+        assert_eq!(
+            Some(StdError::generic_err(
+                "permit(janken) doesn't grant the permission(Shares)"
+            )),
+            res.err()
+        );
 
-            if result == "win" {
-                pass_win = true;
-                assert_eq!(msg_amount, amount * 2 - fee);
-            } else {
-                pass_draw = true;
-                assert_eq!(msg_amount, amount - fee);
-            }
-        }
+        // the owner permission grants all
+        let owner_permit = mock_permit(&secret_key, params(vec![Permission::Owner]));
+        let res = query(&deps, with_permit(&owner_permit, shares_query())).unwrap();
+        let shares: SharesResponse = from_binary(&res).unwrap();
+        assert_eq!(Uint128(0), shares.shares);
+
+        // failed by the permit for another contract
+        let mut other_params = params(vec![Permission::Owner]);
+        other_params.allowed_tokens = vec!["other_contract".into()];
+        let other_permit = mock_permit(&secret_key, other_params);
+        let res = query(&deps, with_permit(&other_permit, shares_query()));
+        assert_eq!(
+            Some(StdError::generic_err(format!(
+                "permit(janken) is not for this contract({})",
+                MOCK_CONTRACT_ADDR
+            ))),
+            res.err()
+        );
+
+        // failed by the params changed after signed
+        let mut forged_permit = permit.clone();
+        forged_permit.params.permissions = vec![Permission::Owner];
+        let res = query(&deps, with_permit(&forged_permit, shares_query()));
+        assert_eq!(
+            Some(StdError::generic_err(
+                "failed to verify the signature of permit(janken)"
+            )),
+            res.err()
+        );
+
+        // failed after revoked
+        let msg = HandleMsg::RevokePermit {
+            permit_name: "janken".to_string(),
+            padding: None,
+        };
+        handle(&mut deps, mock_env(signer.as_str(), &[]), msg).unwrap();
+        let res = query(&deps, with_permit(&owner_permit, shares_query()));
+        assert_eq!(
+            Some(StdError::generic_err(format!(
+                "permit(janken) is revoked by {}",
+                signer
+            ))),
+            res.err()
+        );
     }
 
     #[test]
-    fn query_token_bet() {
+    fn query_my_token_bets() {
         let mut deps = initialize();
 
-        let denom = "uscrt".to_string();
-        let amount = 100;
-        let env = mock_env("bettor_1", &coins(amount.into(), &denom));
-        let id = 123;
-        let hand = 1;
         let msg = HandleMsg::BetToken {
-            id,
-            hand,
+            id: None,
+            hand: 1,
             entropy: "entropy".to_string(),
+            commit: None,
         };
-        handle(&mut deps, env, msg).unwrap();
+        let mut ids = vec![];
+        for sender in &["bettor_1", "bettor_2", "bettor_1", "bettor_1"] {
+            let env = mock_env(*sender, &coins(100, "uscrt"));
+            let res = handle(&mut deps, env, msg.clone()).unwrap();
+            ids.push(assigned_id(&res));
+        }
 
-        let msg = QueryMsg::TokenBet { id };
-        let res = query(&deps, msg).unwrap();
-        let bet: TokenBet = from_binary(&res).unwrap();
-        assert_eq!(denom, bet.denom);
-        assert_eq!(amount, bet.amount);
-        assert_eq!(Hand::from(&hand), bet.hand);
+        let key = viewing_key(&mut deps, "bettor_1");
+        let my_token_bets = |key: &str, start_after, limit| QueryMsg::MyTokenBets {
+            address: "bettor_1".into(),
+            viewing_key: key.to_string(),
+            start_after,
+            limit,
+        };
+        let bet_ids = |msg| {
+            let res: TokenBetsResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+            res.token_bets
+                .iter()
+                .map(|bet| bet.id)
+                .collect::<Vec<u64>>()
+        };
+
+        // only the own bets are listed
+        assert_eq!(
+            vec![ids[0], ids[2], ids[3]],
+            bet_ids(my_token_bets(&key, None, None))
+        );
+
+        // paginated
+        assert_eq!(
+            vec![ids[0], ids[2]],
+            bet_ids(my_token_bets(&key, None, Some(2)))
+        );
+        assert_eq!(
+            vec![ids[3]],
+            bet_ids(my_token_bets(&key, Some(ids[2]), Some(2)))
+        );
+
+        // failed by wrong viewing key
+        let res = query(&deps, my_token_bets("wrong_key", None, None));
+        assert_eq!(Some(StdError::unauthorized()), res.err());
     }
 }
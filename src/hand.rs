@@ -1,5 +1,9 @@
+use cosmwasm_std::{StdError, StdResult};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+use crate::utils::sha_256;
 
 #[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug, JsonSchema)]
 pub enum Hand {
@@ -27,13 +31,18 @@ impl Hand {
     }
 }
 
-impl From<&u8> for Hand {
-    fn from(original: &u8) -> Hand {
+impl TryFrom<&u8> for Hand {
+    type Error = StdError;
+
+    fn try_from(original: &u8) -> StdResult<Hand> {
         match original {
-            1 => Hand::Rock,
-            2 => Hand::Paper,
-            3 => Hand::Scissors,
-            _ => panic!("faild to convert into Hand({})", original),
+            1 => Ok(Hand::Rock),
+            2 => Ok(Hand::Paper),
+            3 => Ok(Hand::Scissors),
+            _ => Err(StdError::generic_err(format!(
+                "invalid hand({}), should be 1(Rock), 2(Paper) or 3(Scissors)",
+                original
+            ))),
         }
     }
 }
@@ -42,23 +51,37 @@ impl From<&u8> for Hand {
 pub struct Hands(Vec<Hand>);
 
 impl Hands {
-    pub fn compete(&self, opponent: &Hands, draw_point: i8) -> MatchResult {
-        let mut point: i8 = 0;
-        let my_hands: Vec<Hand> = self.into();
-        let opponent_hands: Vec<Hand> = opponent.into();
-        for (i, my_hand) in my_hands.iter().enumerate() {
-            let result = my_hand.compete(&opponent_hands[i]);
-            point += result.to_point();
+    pub fn compete(&self, opponent: &Hands, draw_point: i8) -> StdResult<MatchResult> {
+        if self.len() != opponent.len() {
+            return Err(StdError::generic_err(format!(
+                "hands length mismatch, mine: {}, opponent: {}",
+                self.len(),
+                opponent.len()
+            )));
+        }
+        let mut point: i16 = 0;
+        for (my_hand, opponent_hand) in self.0.iter().zip(opponent.0.iter()) {
+            let result = my_hand.compete(opponent_hand);
+            point += result.to_point() as i16;
         }
+        let draw_point = draw_point as i16;
         if point > draw_point {
-            MatchResult::Win
+            Ok(MatchResult::Win)
         } else if point == draw_point {
-            MatchResult::Draw
+            Ok(MatchResult::Draw)
         } else {
-            MatchResult::Lose
+            Ok(MatchResult::Lose)
         }
     }
 
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
     pub fn to_u8_vec(&self) -> Vec<u8> {
         let mut result: Vec<u8> = vec![];
         let hands: Vec<Hand> = self.into();
@@ -75,13 +98,15 @@ impl From<Vec<Hand>> for Hands {
     }
 }
 
-impl From<Vec<u8>> for Hands {
-    fn from(original: Vec<u8>) -> Self {
+impl TryFrom<Vec<u8>> for Hands {
+    type Error = StdError;
+
+    fn try_from(original: Vec<u8>) -> StdResult<Self> {
         let mut hands: Vec<Hand> = vec![];
         for num in original.iter() {
-            hands.push(num.into());
+            hands.push(Hand::try_from(num)?);
         }
-        hands.into()
+        Ok(hands.into())
     }
 }
 
@@ -115,14 +140,28 @@ impl MatchResult {
     }
 }
 
+/// 256 is not a multiple of 3, so the byte of 255 is rejected to give each hand the same
+/// probability. the bytes are rehashed in the rare case that all of them are rejected
 pub fn rand_hand(rng: &[u8]) -> Hand {
-    let num: u8 = rng[0] % 3 + 1;
-    Hand::from(&num)
+    let mut bytes = rng.to_vec();
+    loop {
+        if let Some(byte) = bytes.iter().find(|&&byte| byte < REJECTED_BYTE) {
+            return match byte % 3 {
+                0 => Hand::Rock,
+                1 => Hand::Paper,
+                _ => Hand::Scissors,
+            };
+        }
+        bytes = sha_256(&bytes).to_vec();
+    }
 }
 
+const REJECTED_BYTE: u8 = 255;
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::Prng;
 
     #[test]
     fn hand_compete() {
@@ -148,13 +187,29 @@ mod tests {
         let player1: Hands = vec![Hand::Rock, Hand::Paper, Hand::Scissors, Hand::Rock].into();
         let player2: Hands = vec![Hand::Scissors, Hand::Paper, Hand::Rock, Hand::Scissors].into();
 
-        assert_eq!(MatchResult::Draw, player1.compete(&player2, 1));
-        assert_eq!(MatchResult::Win, player1.compete(&player2, 0));
-        assert_eq!(MatchResult::Lose, player1.compete(&player2, 2));
+        assert_eq!(MatchResult::Draw, player1.compete(&player2, 1).unwrap());
+        assert_eq!(MatchResult::Win, player1.compete(&player2, 0).unwrap());
+        assert_eq!(MatchResult::Lose, player1.compete(&player2, 2).unwrap());
+
+        assert_eq!(MatchResult::Draw, player2.compete(&player1, -1).unwrap());
+        assert_eq!(MatchResult::Win, player2.compete(&player1, -2).unwrap());
+        assert_eq!(MatchResult::Lose, player2.compete(&player1, 0).unwrap());
 
-        assert_eq!(MatchResult::Draw, player2.compete(&player1, -1));
-        assert_eq!(MatchResult::Win, player2.compete(&player1, -2));
-        assert_eq!(MatchResult::Lose, player2.compete(&player1, 0));
+        let player3: Hands = vec![Hand::Rock].into();
+        assert_eq!(true, player1.compete(&player3, 0).is_err());
+    }
+
+    #[test]
+    fn hand_try_from() {
+        assert_eq!(Hand::Rock, Hand::try_from(&1).unwrap());
+        assert_eq!(Hand::Paper, Hand::try_from(&2).unwrap());
+        assert_eq!(Hand::Scissors, Hand::try_from(&3).unwrap());
+        assert_eq!(true, Hand::try_from(&0).is_err());
+        assert_eq!(true, Hand::try_from(&4).is_err());
+
+        let hands = Hands::try_from(vec![1, 2, 3]).unwrap();
+        assert_eq!(vec![1, 2, 3], hands.to_u8_vec());
+        assert_eq!(true, Hands::try_from(vec![1, 2, 4]).is_err());
     }
 
     #[test]
@@ -162,5 +217,75 @@ mod tests {
         rand_hand("1".as_ref());
         rand_hand("2".as_ref());
         rand_hand("3".as_ref());
+        rand_hand(&[]);
+    }
+
+    // the chi-square critical values at the significance level of 0.001
+    const CHI_SQUARE_2_DF: f64 = 13.816;
+    const CHI_SQUARE_255_DF: f64 = 330.52;
+    const DRAWS: usize = 60000;
+
+    fn chi_square(counts: &[usize]) -> f64 {
+        let total: usize = counts.iter().sum();
+        let expected = total as f64 / counts.len() as f64;
+        counts
+            .iter()
+            .map(|&count| (count as f64 - expected).powi(2) / expected)
+            .sum()
+    }
+
+    // the random bytes chained as the contract does on every bet
+    fn rand_bytes_chain() -> Vec<Vec<u8>> {
+        let mut rng = b"prng_seed".to_vec();
+        (0..DRAWS)
+            .map(|i| {
+                rng = Prng::new_rand_bytes(&rng, i.to_string().as_ref());
+                rng.clone()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rand_hand_accepted_bytes() {
+        // every accepted byte is mapped to the hands equally
+        let mut counts = [0; 3];
+        for byte in 0..REJECTED_BYTE {
+            counts[rand_hand(&[byte]) as usize - 1] += 1;
+        }
+        assert_eq!([85, 85, 85], counts);
+
+        // the rejected byte is skipped
+        assert_eq!(Hand::Rock, rand_hand(&[REJECTED_BYTE, 0]));
+        assert_eq!(
+            Hand::Scissors,
+            rand_hand(&[REJECTED_BYTE, REJECTED_BYTE, 2])
+        );
+        rand_hand(&[REJECTED_BYTE; 32]);
+    }
+
+    #[test]
+    fn rand_bytes_distribution() {
+        let mut counts = [0; 256];
+        for bytes in rand_bytes_chain() {
+            for byte in bytes {
+                counts[byte as usize] += 1;
+            }
+        }
+        assert!(chi_square(&counts) < CHI_SQUARE_255_DF);
+    }
+
+    #[test]
+    fn rand_hand_distribution() {
+        let mut counts = [0; 3];
+        for bytes in rand_bytes_chain() {
+            counts[rand_hand(&bytes) as usize - 1] += 1;
+        }
+        assert!(chi_square(&counts) < CHI_SQUARE_2_DF);
+
+        // each hand converges to 1/3 in the long run
+        for count in counts.iter() {
+            let ratio = *count as f64 / DRAWS as f64;
+            assert!((ratio - 1.0 / 3.0).abs() < 0.01);
+        }
     }
 }
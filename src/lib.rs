@@ -1,7 +1,10 @@
 pub mod contract;
 pub mod hand;
+mod migration;
 pub mod msg;
 mod msg_cw721;
+mod msg_snip20;
+pub mod permit;
 pub mod state;
 mod utils;
 mod validation;
@@ -0,0 +1,721 @@
+use cosmwasm_std::{
+    Api, Binary, CanonicalAddr, Coin, Extern, HumanAddr, Order, Querier, StdResult, Storage,
+    Uint128,
+};
+use cosmwasm_storage::{singleton_read, ReadonlyBucket};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::contract::{
+    CONTRACT_VERSION, DEFAULT_LP_FEE_SHARE, DEFAULT_MAX_HANDS, DEFAULT_MAX_NFTS,
+};
+use crate::hand::{Hand, Hands};
+use crate::state::{
+    config, config_read, contract_address, contract_version, contract_version_read,
+    current_epoch_read, held_reserves, held_reserves_read, offeree_offers, offeror_offers, offers,
+    pending_bets, reserves, rotate_epoch, save_offer_count, save_token_bet_count, shares,
+    token_bets, total_shares, ContractStatus, Expiration, Nft, NftCollection, Offer, OfferStatus,
+    PendingBet, State, TokenBet, CONFIG_KEY, PREFIX_OFFERS, PREFIX_PENDING_BETS, PREFIX_TOKEN_BETS,
+};
+use crate::utils::{house_seed, Prng};
+
+/// the records stored by v0.1, which has no version marker
+pub mod v0_1 {
+    use super::*;
+
+    pub const VERSION: u32 = 1;
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+    pub struct State {
+        pub prng_seed: Vec<u8>,
+        pub entropy: Vec<u8>,
+        pub fee_recipient: HumanAddr,
+        pub fee_rate: u64,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+    pub enum OfferStatus {
+        Offered,
+        Accepted,
+        Declined,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+    pub struct Offer {
+        pub id: u64,
+        pub status: OfferStatus,
+        pub offeror: HumanAddr,
+        pub offeree: HumanAddr,
+        pub offeror_nft_contract: HumanAddr,
+        pub offeror_nft: String,
+        pub offeror_code_hash: String,
+        pub offeree_nft_contract: HumanAddr,
+        pub offeree_nft: String,
+        pub offeree_code_hash: String,
+        pub offeror_hands: Hands,
+        pub offeree_hands: Hands,
+        pub offeror_draw_point: i8,
+        pub winner: String,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+    pub struct TokenBet {
+        pub id: u64,
+        pub denom: String,
+        pub amount: u64,
+        pub hand: Hand,
+        pub result: String,
+    }
+}
+
+/// the records stored by v2, before the house reserve, the epochs and the bettors were recorded
+pub mod v2 {
+    use super::*;
+
+    pub const VERSION: u32 = 2;
+
+    /// the token bets are unchanged from v0.1
+    pub use super::v0_1::TokenBet;
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+    pub struct State {
+        pub prng_seed: Vec<u8>,
+        pub entropy: Vec<u8>,
+        pub admin: HumanAddr,
+        pub pending_admin: Option<HumanAddr>,
+        pub fee_recipient: HumanAddr,
+        pub fee_rate: u64,
+        pub max_hands: u32,
+        pub max_nfts: u32,
+        pub contract_status: ContractStatus,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+    pub struct Offer {
+        pub id: u64,
+        pub status: OfferStatus,
+        pub offeror: HumanAddr,
+        pub offeree: HumanAddr,
+        pub offeror_nfts: Vec<Nft>,
+        pub offeree_nfts: Vec<Nft>,
+        pub open: bool,
+        pub offeree_collection: Option<NftCollection>,
+        pub offeror_hands: Hands,
+        pub offeree_hands: Hands,
+        pub offeror_draw_point: i8,
+        pub expires: Expiration,
+        pub escrow: bool,
+        pub offeror_funds: Vec<Coin>,
+        pub offeree_funds: Vec<Coin>,
+        pub counter_of: Option<u64>,
+        pub countered_by: Option<u64>,
+        pub winner: String,
+    }
+}
+
+/// the records stored by v3, whose pending bets were drawn when settled
+pub mod v3 {
+    use super::*;
+
+    pub const VERSION: u32 = 3;
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+    pub struct PendingBet {
+        pub id: u64,
+        pub bettor: CanonicalAddr,
+        pub denom: String,
+        pub amount: Uint128,
+        pub hand: Hand,
+        pub height: u64,
+    }
+}
+
+// the fee recipient was the only privileged address in v0.1, so it becomes the admin
+impl From<v0_1::State> for State {
+    fn from(state: v0_1::State) -> State {
+        State {
+            prng_seed: state.prng_seed,
+            entropy: state.entropy,
+            admin: state.fee_recipient.clone(),
+            pending_admin: None,
+            fee_recipient: state.fee_recipient,
+            fee_rate: state.fee_rate,
+            lp_fee_share: DEFAULT_LP_FEE_SHARE,
+            max_hands: DEFAULT_MAX_HANDS,
+            max_nfts: DEFAULT_MAX_NFTS,
+            contract_status: ContractStatus::Normal,
+        }
+    }
+}
+
+impl From<v0_1::OfferStatus> for OfferStatus {
+    fn from(status: v0_1::OfferStatus) -> OfferStatus {
+        match status {
+            v0_1::OfferStatus::Offered => OfferStatus::Offered,
+            v0_1::OfferStatus::Accepted => OfferStatus::Accepted,
+            v0_1::OfferStatus::Declined => OfferStatus::Declined,
+        }
+    }
+}
+
+impl From<v0_1::Offer> for Offer {
+    fn from(offer: v0_1::Offer) -> Offer {
+        Offer {
+            id: offer.id,
+            status: offer.status.into(),
+            offeror: offer.offeror,
+            offeree: offer.offeree,
+            offeror_nfts: vec![Nft {
+                contract: offer.offeror_nft_contract,
+                code_hash: offer.offeror_code_hash,
+                token_id: offer.offeror_nft,
+            }],
+            offeree_nfts: vec![Nft {
+                contract: offer.offeree_nft_contract,
+                code_hash: offer.offeree_code_hash,
+                token_id: offer.offeree_nft,
+            }],
+            open: false,
+            offeree_collection: None,
+            offeror_hands: offer.offeror_hands,
+            offeree_hands: offer.offeree_hands,
+            offeror_draw_point: offer.offeror_draw_point,
+            expires: Expiration::Never,
+            escrow: false,
+            offeror_funds: vec![],
+            offeree_funds: vec![],
+            counter_of: None,
+            countered_by: None,
+            winner: offer.winner,
+            offeror_published: false,
+            offeree_published: false,
+        }
+    }
+}
+
+// v0.1 didn't record the bettors, so its bets are owned by nobody
+impl From<v0_1::TokenBet> for TokenBet {
+    fn from(bet: v0_1::TokenBet) -> TokenBet {
+        TokenBet {
+            id: bet.id,
+            bettor: CanonicalAddr(Binary(vec![])),
+            denom: bet.denom,
+            amount: Uint128(bet.amount as u128),
+            hand: bet.hand,
+            result: bet.result,
+            epoch: 0,
+            entropy: Binary(vec![]),
+        }
+    }
+}
+
+impl From<v2::State> for State {
+    fn from(state: v2::State) -> State {
+        State {
+            prng_seed: state.prng_seed,
+            entropy: state.entropy,
+            admin: state.admin,
+            pending_admin: state.pending_admin,
+            fee_recipient: state.fee_recipient,
+            fee_rate: state.fee_rate,
+            lp_fee_share: DEFAULT_LP_FEE_SHARE,
+            max_hands: state.max_hands,
+            max_nfts: state.max_nfts,
+            contract_status: state.contract_status,
+        }
+    }
+}
+
+impl From<v2::Offer> for Offer {
+    fn from(offer: v2::Offer) -> Offer {
+        Offer {
+            id: offer.id,
+            status: offer.status,
+            offeror: offer.offeror,
+            offeree: offer.offeree,
+            offeror_nfts: offer.offeror_nfts,
+            offeree_nfts: offer.offeree_nfts,
+            open: offer.open,
+            offeree_collection: offer.offeree_collection,
+            offeror_hands: offer.offeror_hands,
+            offeree_hands: offer.offeree_hands,
+            offeror_draw_point: offer.offeror_draw_point,
+            expires: offer.expires,
+            escrow: offer.escrow,
+            offeror_funds: offer.offeror_funds,
+            offeree_funds: offer.offeree_funds,
+            counter_of: offer.counter_of,
+            countered_by: offer.countered_by,
+            winner: offer.winner,
+            offeror_published: false,
+            offeree_published: false,
+        }
+    }
+}
+
+/// returns the stored version, v0.1 is assumed if missing
+pub fn stored_version<S: Storage>(storage: &S) -> StdResult<u32> {
+    Ok(contract_version_read(storage)
+        .may_load()?
+        .unwrap_or(v0_1::VERSION))
+}
+
+pub fn load_v0_1_config<S: Storage>(storage: &S) -> StdResult<v0_1::State> {
+    singleton_read(storage, CONFIG_KEY).load()
+}
+
+pub fn load_v2_config<S: Storage>(storage: &S) -> StdResult<v2::State> {
+    singleton_read(storage, CONFIG_KEY).load()
+}
+
+/// upgrades the config, the offers and the token bets stored by v0.1 in place.
+/// the ids were given by clients in v0.1, so the id counters restart from the largest ones and
+/// the offers are indexed by the players. v0.1 held no coins but the house bankroll, so the
+/// whole balance of the contract becomes the reserve, whose shares are owned by the admin
+pub fn migrate_v0_1<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    contract_addr: &HumanAddr,
+) -> StdResult<()> {
+    let state: State = load_v0_1_config(&deps.storage)?.into();
+    config(&mut deps.storage).save(&state)?;
+    contract_address(&mut deps.storage).save(contract_addr)?;
+    // the bets of v0.1 were played without a committed seed
+    rotate_epoch(&mut deps.storage, &house_seed(&state.prng_seed))?;
+
+    let old_offers: Vec<v0_1::Offer> =
+        ReadonlyBucket::<S, v0_1::Offer>::new(PREFIX_OFFERS, &deps.storage)
+            .range(None, None, Order::Ascending)
+            .map(|item| item.map(|(_, offer)| offer))
+            .collect::<StdResult<_>>()?;
+    let mut offer_count = 0;
+    for old_offer in old_offers {
+        let offer: Offer = old_offer.into();
+        let key = offer.id.to_be_bytes();
+        let offeror = deps.api.canonical_address(&offer.offeror)?;
+        let offeree = deps.api.canonical_address(&offer.offeree)?;
+        offeror_offers(&mut deps.storage, &offeror).save(&key, &offer.id)?;
+        offeree_offers(&mut deps.storage, &offeree).save(&key, &offer.id)?;
+        offer_count = offer_count.max(offer.id);
+        offers(&mut deps.storage).save(&key, &offer)?;
+    }
+    save_offer_count(&mut deps.storage, offer_count)?;
+
+    let old_bets: Vec<v0_1::TokenBet> =
+        ReadonlyBucket::<S, v0_1::TokenBet>::new(PREFIX_TOKEN_BETS, &deps.storage)
+            .range(None, None, Order::Ascending)
+            .map(|item| item.map(|(_, bet)| bet))
+            .collect::<StdResult<_>>()?;
+    let mut token_bet_count = 0;
+    for old_bet in old_bets {
+        let bet: TokenBet = old_bet.into();
+        token_bet_count = token_bet_count.max(bet.id);
+        token_bets(&mut deps.storage).save(&bet.id.to_be_bytes(), &bet)?;
+    }
+    save_token_bet_count(&mut deps.storage, token_bet_count)?;
+
+    fund_reserves(deps, contract_addr, &state.admin, &[])?;
+    contract_version(&mut deps.storage).save(&CONTRACT_VERSION)
+}
+
+/// upgrades the config, the offers and the token bets stored by v2 in place. the ids and the
+/// indices are kept. v2 held the coins of the pending offers besides the house bankroll, so the
+/// rest of the balance becomes the reserve, whose shares are owned by the admin
+pub fn migrate_v2<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    contract_addr: &HumanAddr,
+) -> StdResult<()> {
+    let state: State = load_v2_config(&deps.storage)?.into();
+    config(&mut deps.storage).save(&state)?;
+    contract_address(&mut deps.storage).save(contract_addr)?;
+    // the bets of v2 were played without a committed seed
+    rotate_epoch(&mut deps.storage, &house_seed(&state.prng_seed))?;
+
+    let old_offers: Vec<v2::Offer> =
+        ReadonlyBucket::<S, v2::Offer>::new(PREFIX_OFFERS, &deps.storage)
+            .range(None, None, Order::Ascending)
+            .map(|item| item.map(|(_, offer)| offer))
+            .collect::<StdResult<_>>()?;
+    let mut held = vec![];
+    for old_offer in old_offers {
+        let offer: Offer = old_offer.into();
+        if offer.status == OfferStatus::Offered {
+            held.extend(offer.offeror_funds.iter().cloned());
+        }
+        offers(&mut deps.storage).save(&offer.id.to_be_bytes(), &offer)?;
+    }
+
+    let old_bets: Vec<v2::TokenBet> =
+        ReadonlyBucket::<S, v2::TokenBet>::new(PREFIX_TOKEN_BETS, &deps.storage)
+            .range(None, None, Order::Ascending)
+            .map(|item| item.map(|(_, bet)| bet))
+            .collect::<StdResult<_>>()?;
+    for old_bet in old_bets {
+        let bet: TokenBet = old_bet.into();
+        token_bets(&mut deps.storage).save(&bet.id.to_be_bytes(), &bet)?;
+    }
+
+    fund_reserves(deps, contract_addr, &state.admin, &held)?;
+    contract_version(&mut deps.storage).save(&CONTRACT_VERSION)
+}
+
+/// fixes the inputs of the pending bets stored by v3 as the later bets do at the bet, and
+/// records the reserve held for them
+pub fn migrate_v3<S: Storage>(storage: &mut S) -> StdResult<()> {
+    let state: State = config_read(storage).load()?;
+    let epoch = current_epoch_read(storage).load()?;
+    let old_bets: Vec<v3::PendingBet> =
+        ReadonlyBucket::<S, v3::PendingBet>::new(PREFIX_PENDING_BETS, storage)
+            .range(None, None, Order::Ascending)
+            .map(|item| item.map(|(_, bet)| bet))
+            .collect::<StdResult<_>>()?;
+    for old_bet in old_bets {
+        let denom = old_bet.denom.as_bytes();
+        let held = held_reserves_read(storage)
+            .may_load(denom)?
+            .unwrap_or_default()
+            .u128();
+        held_reserves(storage).save(denom, &Uint128(held + old_bet.amount.u128()))?;
+        let bet = PendingBet {
+            id: old_bet.id,
+            bettor: old_bet.bettor,
+            denom: old_bet.denom.clone(),
+            amount: old_bet.amount,
+            hand: old_bet.hand,
+            height: old_bet.height,
+            epoch,
+            entropy: Binary(Prng::new_rand_bytes(
+                &state.entropy,
+                &old_bet.id.to_be_bytes(),
+            )),
+        };
+        pending_bets(storage).save(&bet.id.to_be_bytes(), &bet)?;
+    }
+    contract_version(storage).save(&CONTRACT_VERSION)
+}
+
+// the balance of the contract except the held coins becomes the reserve owned by the admin
+fn fund_reserves<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    contract_addr: &HumanAddr,
+    admin: &HumanAddr,
+    held: &[Coin],
+) -> StdResult<()> {
+    let admin = deps.api.canonical_address(admin)?;
+    for balance in deps.querier.query_all_balances(contract_addr)? {
+        let denom = &balance.denom;
+        let held_amount: u128 = held
+            .iter()
+            .filter(|coin| &coin.denom == denom)
+            .map(|coin| coin.amount.u128())
+            .sum();
+        let reserve = Uint128(balance.amount.u128().saturating_sub(held_amount));
+        if reserve.is_zero() {
+            continue;
+        }
+        reserves(&mut deps.storage).save(denom.as_bytes(), &reserve)?;
+        shares(&mut deps.storage, denom).save(admin.as_slice(), &reserve)?;
+        total_shares(&mut deps.storage).save(denom.as_bytes(), &reserve)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{handle, init};
+    use crate::mock::mock_dependencies;
+    use crate::msg::{HandleMsg, InitMsg};
+    use crate::state::{
+        epochs_read, next_offer_id, next_token_bet_id, offeree_offers_read, offers_read,
+        pending_bets_read, reserves_read, shares_read, token_bets_read,
+    };
+    use crate::utils::sha_256;
+    use cosmwasm_std::testing::{mock_env, MOCK_CONTRACT_ADDR};
+    use cosmwasm_std::{coins, StdError, Uint128};
+    use cosmwasm_storage::{bucket, singleton};
+
+    #[test]
+    fn migrate_v0_1_records() {
+        let balance = coins(10000, "uscrt");
+        let mut deps = mock_dependencies(
+            &[(&HumanAddr::from(MOCK_CONTRACT_ADDR), &balance[..])],
+            None,
+        );
+
+        let old_state = v0_1::State {
+            prng_seed: b"prng_seed".to_vec(),
+            entropy: b"entropy".to_vec(),
+            fee_recipient: "owner".into(),
+            fee_rate: 300,
+        };
+        singleton(&mut deps.storage, CONFIG_KEY)
+            .save(&old_state)
+            .unwrap();
+        let old_offer = v0_1::Offer {
+            id: 7,
+            status: v0_1::OfferStatus::Accepted,
+            offeror: "nft_owner_1".into(),
+            offeree: "nft_owner_2".into(),
+            offeror_nft_contract: "offeror_contract".into(),
+            offeror_nft: "nft_id_1".to_string(),
+            offeror_code_hash: "offeror_code_hash".to_string(),
+            offeree_nft_contract: "offeree_contract".into(),
+            offeree_nft: "nft_id_2".to_string(),
+            offeree_code_hash: "offeree_code_hash".to_string(),
+            offeror_hands: vec![Hand::Rock, Hand::Paper].into(),
+            offeree_hands: vec![Hand::Scissors, Hand::Rock].into(),
+            offeror_draw_point: 0,
+            winner: "offeree".to_string(),
+        };
+        bucket(PREFIX_OFFERS, &mut deps.storage)
+            .save(&7u64.to_be_bytes(), &old_offer)
+            .unwrap();
+        let old_bet = v0_1::TokenBet {
+            id: 3,
+            denom: "uscrt".to_string(),
+            amount: 100,
+            hand: Hand::Paper,
+            result: "win".to_string(),
+        };
+        bucket(PREFIX_TOKEN_BETS, &mut deps.storage)
+            .save(&3u64.to_be_bytes(), &old_bet)
+            .unwrap();
+        assert_eq!(v0_1::VERSION, stored_version(&deps.storage).unwrap());
+
+        // failed by non admin
+        let res = handle(&mut deps, mock_env("anyone", &[]), HandleMsg::Migrate {});
+        assert_eq!(Some(StdError::unauthorized()), res.err());
+
+        // succeed
+        handle(&mut deps, mock_env("owner", &[]), HandleMsg::Migrate {}).unwrap();
+        assert_eq!(CONTRACT_VERSION, stored_version(&deps.storage).unwrap());
+
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(HumanAddr::from("owner"), state.admin);
+        assert_eq!(old_state.entropy, state.entropy);
+        assert_eq!(old_state.fee_rate, state.fee_rate);
+
+        let offer = offers_read(&deps.storage)
+            .load(&7u64.to_be_bytes())
+            .unwrap();
+        assert_eq!(OfferStatus::Accepted, offer.status);
+        assert_eq!(old_offer.offeree_hands, offer.offeree_hands);
+        let expected = vec![Nft {
+            contract: "offeror_contract".into(),
+            code_hash: "offeror_code_hash".to_string(),
+            token_id: "nft_id_1".to_string(),
+        }];
+        assert_eq!(expected, offer.offeror_nfts);
+        let offeree = deps
+            .api
+            .canonical_address(&HumanAddr::from("nft_owner_2"))
+            .unwrap();
+        let indexed = offeree_offers_read(&deps.storage, &offeree)
+            .load(&7u64.to_be_bytes())
+            .unwrap();
+        assert_eq!(7, indexed);
+
+        let bet = token_bets_read(&deps.storage)
+            .load(&3u64.to_be_bytes())
+            .unwrap();
+        assert_eq!(TokenBet::from(old_bet), bet);
+
+        // the house seed is committed as the seed of the first epoch
+        let epoch = epochs_read(&deps.storage)
+            .load(&1u64.to_be_bytes())
+            .unwrap();
+        assert_eq!(
+            Binary(sha_256(&house_seed(&old_state.prng_seed)).to_vec()),
+            epoch.commitment
+        );
+        assert_eq!(None, epoch.seed);
+
+        // the balance of the contract is the reserve owned by the admin
+        let reserve = reserves_read(&deps.storage).load(b"uscrt").unwrap();
+        assert_eq!(Uint128(10000), reserve);
+        let admin = deps.api.canonical_address(&state.admin).unwrap();
+        let owned = shares_read(&deps.storage, "uscrt")
+            .load(admin.as_slice())
+            .unwrap();
+        assert_eq!(Uint128(10000), owned);
+
+        // the ids continue from the migrated ones
+        assert_eq!(8, next_offer_id(&mut deps.storage).unwrap());
+        assert_eq!(4, next_token_bet_id(&mut deps.storage).unwrap());
+
+        // failed by migrating again
+        let res = handle(&mut deps, mock_env("owner", &[]), HandleMsg::Migrate {});
+        assert_eq!(
+            Some(StdError::generic_err(format!(
+                "already migrated to version({})",
+                CONTRACT_VERSION
+            ))),
+            res.err()
+        );
+    }
+
+    #[test]
+    fn migrate_v2_records() {
+        let balance = coins(11000, "uscrt");
+        let mut deps = mock_dependencies(
+            &[(&HumanAddr::from(MOCK_CONTRACT_ADDR), &balance[..])],
+            None,
+        );
+
+        let old_state = v2::State {
+            prng_seed: b"prng_seed".to_vec(),
+            entropy: b"entropy".to_vec(),
+            admin: "admin".into(),
+            pending_admin: None,
+            fee_recipient: "recipient".into(),
+            fee_rate: 300,
+            max_hands: 5,
+            max_nfts: 3,
+            contract_status: ContractStatus::StopNewGames,
+        };
+        singleton(&mut deps.storage, CONFIG_KEY)
+            .save(&old_state)
+            .unwrap();
+        contract_version(&mut deps.storage)
+            .save(&v2::VERSION)
+            .unwrap();
+        let old_offer = v2::Offer {
+            id: 7,
+            status: OfferStatus::Offered,
+            offeror: "nft_owner_1".into(),
+            offeree: "nft_owner_2".into(),
+            offeror_nfts: vec![Nft {
+                contract: "offeror_contract".into(),
+                code_hash: "offeror_code_hash".to_string(),
+                token_id: "nft_id_1".to_string(),
+            }],
+            offeree_nfts: vec![],
+            open: false,
+            offeree_collection: None,
+            offeror_hands: vec![Hand::Rock, Hand::Paper].into(),
+            offeree_hands: Vec::<Hand>::new().into(),
+            offeror_draw_point: 0,
+            expires: Expiration::Never,
+            escrow: false,
+            offeror_funds: coins(1000, "uscrt"),
+            offeree_funds: vec![],
+            counter_of: None,
+            countered_by: None,
+            winner: String::new(),
+        };
+        bucket(PREFIX_OFFERS, &mut deps.storage)
+            .save(&7u64.to_be_bytes(), &old_offer)
+            .unwrap();
+        let old_bet = v2::TokenBet {
+            id: 3,
+            denom: "uscrt".to_string(),
+            amount: 100,
+            hand: Hand::Paper,
+            result: "win".to_string(),
+        };
+        bucket(PREFIX_TOKEN_BETS, &mut deps.storage)
+            .save(&3u64.to_be_bytes(), &old_bet)
+            .unwrap();
+        assert_eq!(v2::VERSION, stored_version(&deps.storage).unwrap());
+
+        // failed by non admin
+        let res = handle(&mut deps, mock_env("recipient", &[]), HandleMsg::Migrate {});
+        assert_eq!(Some(StdError::unauthorized()), res.err());
+
+        // succeed even if the contract is stopped
+        handle(&mut deps, mock_env("admin", &[]), HandleMsg::Migrate {}).unwrap();
+        assert_eq!(CONTRACT_VERSION, stored_version(&deps.storage).unwrap());
+
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(HumanAddr::from("admin"), state.admin);
+        assert_eq!(DEFAULT_LP_FEE_SHARE, state.lp_fee_share);
+        assert_eq!(old_state.max_hands, state.max_hands);
+        assert_eq!(old_state.contract_status, state.contract_status);
+
+        let offer = offers_read(&deps.storage)
+            .load(&7u64.to_be_bytes())
+            .unwrap();
+        assert_eq!(Offer::from(old_offer), offer);
+        assert!(!offer.offeror_published && !offer.offeree_published);
+
+        let bet = token_bets_read(&deps.storage)
+            .load(&3u64.to_be_bytes())
+            .unwrap();
+        assert_eq!(TokenBet::from(old_bet), bet);
+
+        let epoch = epochs_read(&deps.storage)
+            .load(&1u64.to_be_bytes())
+            .unwrap();
+        assert_eq!(
+            Binary(sha_256(&house_seed(&old_state.prng_seed)).to_vec()),
+            epoch.commitment
+        );
+
+        // the coins of the pending offer are held apart from the reserve
+        let reserve = reserves_read(&deps.storage).load(b"uscrt").unwrap();
+        assert_eq!(Uint128(10000), reserve);
+        let admin = deps.api.canonical_address(&state.admin).unwrap();
+        let owned = shares_read(&deps.storage, "uscrt")
+            .load(admin.as_slice())
+            .unwrap();
+        assert_eq!(Uint128(10000), owned);
+
+        // failed by migrating again
+        let res = handle(&mut deps, mock_env("admin", &[]), HandleMsg::Migrate {});
+        assert_eq!(
+            Some(StdError::generic_err(format!(
+                "already migrated to version({})",
+                CONTRACT_VERSION
+            ))),
+            res.err()
+        );
+    }
+
+    #[test]
+    fn migrate_v3_records() {
+        let mut deps = mock_dependencies(&[], None);
+        let msg = InitMsg {
+            prng_seed: "prng_seed".to_string(),
+            fee_recipient: None,
+            fee_rate: None,
+        };
+        init(&mut deps, mock_env("admin", &[]), msg).unwrap();
+        contract_version(&mut deps.storage)
+            .save(&v3::VERSION)
+            .unwrap();
+        let old_bet = v3::PendingBet {
+            id: 5,
+            bettor: deps.api.canonical_address(&"bettor".into()).unwrap(),
+            denom: "uscrt".to_string(),
+            amount: Uint128(100),
+            hand: Hand::Rock,
+            height: 12345,
+        };
+        bucket(PREFIX_PENDING_BETS, &mut deps.storage)
+            .save(&5u64.to_be_bytes(), &old_bet)
+            .unwrap();
+
+        // failed by non admin
+        let res = handle(&mut deps, mock_env("anyone", &[]), HandleMsg::Migrate {});
+        assert_eq!(Some(StdError::unauthorized()), res.err());
+
+        // succeed
+        handle(&mut deps, mock_env("admin", &[]), HandleMsg::Migrate {}).unwrap();
+        assert_eq!(CONTRACT_VERSION, stored_version(&deps.storage).unwrap());
+
+        // the inputs of the pending bet are fixed in the current epoch
+        let bet = pending_bets_read(&deps.storage)
+            .load(&5u64.to_be_bytes())
+            .unwrap();
+        assert_eq!(old_bet.bettor, bet.bettor);
+        assert_eq!(old_bet.amount, bet.amount);
+        assert_eq!(old_bet.hand, bet.hand);
+        assert_eq!(old_bet.height, bet.height);
+        assert_eq!(1, bet.epoch);
+        assert_ne!(Binary(vec![]), bet.entropy);
+
+        // the stake of the pending bet is held
+        let held = held_reserves_read(&deps.storage).load(b"uscrt").unwrap();
+        assert_eq!(Uint128(100), held);
+    }
+}
@@ -1,11 +1,14 @@
 use cosmwasm_std::testing::{BankQuerier, MockApi, MockStorage};
 use cosmwasm_std::{
-    from_binary, from_slice, to_binary, Coin, Empty, Extern, HumanAddr, Querier, QuerierResult,
-    QueryRequest, SystemError, WasmQuery,
+    from_binary, from_slice, to_binary, Binary, Coin, Empty, Extern, HumanAddr, Querier,
+    QuerierResult, QueryRequest, SystemError, WasmQuery,
 };
+use k256::ecdsa::signature::{Signature as _, Signer};
+use k256::ecdsa::{Signature, SigningKey};
 use std::collections::HashMap;
 
 use crate::msg_cw721::{QueryAnswer, QueryMsg as Cw721QueryMsg};
+use crate::permit::{Permit, PermitParams, PermitSignature, PubKey};
 
 pub fn mock_dependencies(
     balances: &[(&HumanAddr, &[Coin])],
@@ -13,11 +16,28 @@ pub fn mock_dependencies(
 ) -> Extern<MockStorage, MockApi, MockQuerier> {
     Extern {
         storage: MockStorage::default(),
-        api: MockApi::new(20),
+        // long enough for the bech32 addresses of the permit signers
+        api: MockApi::new(45),
         querier: MockQuerier::new(balances, owners),
     }
 }
 
+/// signs the permit as the wallet does, the signer is derived from the secret key
+pub fn mock_permit(secret_key: &[u8], params: PermitParams) -> Permit {
+    let signing_key = SigningKey::from_bytes(secret_key).unwrap();
+    let signature: Signature = signing_key.sign(params.sign_doc().unwrap().as_slice());
+    Permit {
+        params,
+        signature: PermitSignature {
+            pub_key: PubKey {
+                r#type: "tendermint/PubKeySecp256k1".to_string(),
+                value: Binary(signing_key.verifying_key().to_bytes().to_vec()),
+            },
+            signature: Binary(signature.as_bytes().to_vec()),
+        },
+    }
+}
+
 pub struct MockQuerier {
     bank: BankQuerier,
     wasm: WasmQuerier,
@@ -37,6 +57,11 @@ impl MockQuerier {
             wasm: WasmQuerier { owners },
         }
     }
+
+    /// moves the token as the nft contract does on transfer
+    pub fn set_owner(&mut self, token_id: &str, owner: HumanAddr) {
+        self.wasm.owners.insert(token_id.to_string(), owner);
+    }
 }
 
 impl Querier for MockQuerier {
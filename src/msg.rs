@@ -1,7 +1,11 @@
-use cosmwasm_std::HumanAddr;
+use cosmwasm_std::{Binary, Coin, HumanAddr, Uint128};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::hand::Hands;
+use crate::permit::Permit;
+use crate::state::{ContractStatus, Expiration, Nft, NftCollection, Offer, OfferStatus, TokenBet};
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InitMsg {
     pub prng_seed: String,
@@ -13,37 +17,195 @@ pub struct InitMsg {
 #[serde(rename_all = "snake_case")]
 pub enum HandleMsg {
     MakeOffer {
-        id: u64,
-        offeree: HumanAddr,
-        offeror_nft_contract: HumanAddr,
-        offeror_nft: String,
-        offeror_code_hash: String,
-        offeree_nft_contract: HumanAddr,
-        offeree_nft: String,
-        offeree_code_hash: String,
+        /// optional idempotency key, the offer id is assigned by contract
+        id: Option<u64>,
+        /// the offer is open to anyone if omitted
+        offeree: Option<HumanAddr>,
+        offeror_nfts: Vec<Nft>,
+        /// should be omitted for the open offer
+        offeree_nfts: Option<Vec<Nft>>,
+        /// the collection acceptable for the open offer
+        offeree_collection: Option<NftCollection>,
+        /// the coins which the offeree should send on accept, the offeror coins are sent with
+        /// this msg
+        offeree_funds: Option<Vec<Coin>>,
         offeror_hands: Vec<u8>,
         offeror_draw_point: i8,
+        expires: Option<Expiration>,
+        escrow: Option<bool>,
     },
     AcceptOffer {
         id: u64,
         offeree_hands: Vec<u8>,
+        /// the token id to stake for the open offer
+        offeree_nft: Option<String>,
     },
     DeclineOffer {
         id: u64,
     },
-    BetToken {
+    /// the offeree makes the offer to the offeror in return
+    CounterOffer {
+        /// the offer to counter
+        id: u64,
+        /// the nfts to stake instead of the requested ones
+        offeror_nfts: Option<Vec<Nft>>,
+        /// the nfts to request instead of the offered ones
+        offeree_nfts: Option<Vec<Nft>>,
+        /// the coins to request instead of the offered ones
+        offeree_funds: Option<Vec<Coin>>,
+        offeror_hands: Vec<u8>,
+        offeror_draw_point: i8,
+        expires: Option<Expiration>,
+        escrow: Option<bool>,
+    },
+    CancelOffer {
         id: u64,
+    },
+    /// the participant publishes own side of the settled match
+    PublishOffer {
+        id: u64,
+    },
+    RegisterReceiveNft {
+        nft_contract: HumanAddr,
+        code_hash: String,
+    },
+    ReceiveNft {
+        /// the previous owner of the sent token
+        sender: HumanAddr,
+        token_id: String,
+        msg: Option<Binary>,
+    },
+    BatchReceiveNft {
+        /// the address which sent the tokens
+        sender: HumanAddr,
+        /// the previous owner of the sent tokens
+        from: HumanAddr,
+        token_ids: Vec<String>,
+        msg: Option<Binary>,
+    },
+    BetToken {
+        /// optional idempotency key, the bet id is assigned by contract
+        id: Option<u64>,
         hand: u8,
         entropy: String,
+        /// optional, only records the bet to be settled by `SettleBet` in a later block
+        commit: Option<bool>,
     },
+    /// anyone can settle the committed bet in a later block, or refund it after the timeout
+    SettleBet {
+        id: u64,
+    },
+    /// the admin whitelists a snip20 token to bet, the contract address is the denom of it
+    RegisterToken {
+        token_contract: HumanAddr,
+        code_hash: String,
+    },
+    Receive {
+        /// the address which sent the tokens
+        sender: HumanAddr,
+        /// the previous owner of the sent tokens
+        from: HumanAddr,
+        amount: Uint128,
+        msg: Option<Binary>,
+    },
+    /// only the fee recipient can withdraw up to the accrued fee
     WithdrawFee {
         denom: String,
-        amount: u64,
+        amount: Uint128,
+    },
+    /// adds the sent funds to the house reserve in return for the shares of it
+    Deposit {},
+    /// redeems the shares for the coins of the reserve
+    Withdraw {
+        denom: String,
+        shares: Uint128,
+    },
+    /// the admin reveals the seed of the current epoch and commits to the seed of the next one
+    RotateSeed {
+        seed: String,
+    },
+    /// the admin sets the limits of the token bets in the denom
+    SetBetLimits {
+        denom: String,
+        min_bet: Uint128,
+        max_bet: Option<Uint128>,
+        max_payout_rate: u64,
     },
     GenerateViewingKey {
         entropy: String,
         padding: Option<String>,
     },
+    /// replaces the viewing key of the sender with the given one
+    SetViewingKey {
+        key: String,
+        padding: Option<String>,
+    },
+    /// the viewing key of the sender can't be used until another one is generated or set
+    RevokeViewingKey {
+        padding: Option<String>,
+    },
+    /// the permit of the name signed by the sender can't be used any more
+    RevokePermit {
+        permit_name: String,
+        padding: Option<String>,
+    },
+    /// only the admin can update, the omitted fields are unchanged
+    UpdateConfig {
+        fee_rate: Option<u64>,
+        fee_recipient: Option<HumanAddr>,
+        lp_fee_share: Option<u64>,
+        max_hands: Option<u32>,
+        max_nfts: Option<u32>,
+    },
+    /// the admin proposes the next admin, which takes over by `AcceptAdmin`
+    ChangeAdmin {
+        address: HumanAddr,
+    },
+    AcceptAdmin {},
+    /// only the admin can set
+    SetContractStatus {
+        level: ContractStatus,
+    },
+    /// upgrades the records stored by the older version in place, only the admin can migrate.
+    /// the fee recipient is regarded as the admin of v0.1
+    Migrate {},
+}
+
+/// the msg embedded in `SendNft` or `BatchSendNft`, the sent nfts are staked as the sender side
+/// nfts
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiveNftMsg {
+    MakeOffer {
+        /// optional idempotency key, the offer id is assigned by contract
+        id: Option<u64>,
+        offeree: Option<HumanAddr>,
+        offeree_nfts: Option<Vec<Nft>>,
+        offeree_collection: Option<NftCollection>,
+        offeree_funds: Option<Vec<Coin>>,
+        offeror_hands: Vec<u8>,
+        offeror_draw_point: i8,
+        expires: Option<Expiration>,
+    },
+    AcceptOffer {
+        id: u64,
+        offeree_hands: Vec<u8>,
+    },
+}
+
+/// the msg embedded in `Send` of a whitelisted snip20 token, the sent tokens are bet or deposited
+/// by the previous owner
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiveTokenMsg {
+    BetToken {
+        /// optional idempotency key, the bet id is assigned by contract
+        id: Option<u64>,
+        hand: u8,
+        entropy: String,
+        commit: Option<bool>,
+    },
+    Deposit {},
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -54,13 +216,165 @@ pub enum QueryMsg {
         address: Option<HumanAddr>,
         viewing_key: Option<String>,
     },
-    // Offers {},
+    Offers {
+        address: HumanAddr,
+        viewing_key: String,
+        role: OfferRole,
+        status: Option<OfferStatus>,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
     TokenBet {
         id: u64,
+        address: HumanAddr,
+        viewing_key: String,
+    },
+    MyTokenBets {
+        address: HumanAddr,
+        viewing_key: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    Config {},
+    ContractStatus {},
+    FeeLedger {
+        denom: String,
+    },
+    Shares {
+        address: HumanAddr,
+        viewing_key: String,
+        denom: String,
+    },
+    BetLimits {
+        denom: String,
+    },
+    /// the current epoch if the id is omitted
+    Epoch {
+        id: Option<u64>,
+    },
+    /// the private queries authenticated by the permit instead of the viewing key
+    WithPermit {
+        permit: Permit,
+        query: QueryWithPermit,
     },
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryWithPermit {
+    Offer {
+        id: u64,
+    },
+    Offers {
+        role: OfferRole,
+        status: Option<OfferStatus>,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    TokenBet {
+        id: u64,
+    },
+    MyTokenBets {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    Shares {
+        denom: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OfferRole {
+    /// the offers made by the address
+    Offeror,
+    /// the offers made to the address
+    Offeree,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct OffersResponse {
-    pub ids: Vec<u64>,
+    pub offers: Vec<Offer>,
+}
+
+/// the offer seen by anyone but the participants
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PublicOfferResponse {
+    pub id: u64,
+    pub status: OfferStatus,
+    pub open: bool,
+    pub expires: Expiration,
+    /// the terms of the open offer, shown while anyone can accept it
+    pub terms: Option<OpenOfferTerms>,
+    /// shown with the terms or any published side
+    pub offeror_draw_point: Option<i8>,
+    /// the sides of the settled match which the participants published
+    pub offeror: Option<PublishedSide>,
+    pub offeree: Option<PublishedSide>,
+    /// shown if any participant published
+    pub winner: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OpenOfferTerms {
+    pub offeror: HumanAddr,
+    pub offeror_nfts: Vec<Nft>,
+    pub offeror_funds: Vec<Coin>,
+    pub offeree_collection: Option<NftCollection>,
+    pub offeree_funds: Vec<Coin>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PublishedSide {
+    pub player: HumanAddr,
+    pub nfts: Vec<Nft>,
+    pub funds: Vec<Coin>,
+    pub hands: Hands,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TokenBetsResponse {
+    pub token_bets: Vec<TokenBet>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ConfigResponse {
+    pub admin: HumanAddr,
+    pub pending_admin: Option<HumanAddr>,
+    pub fee_recipient: HumanAddr,
+    pub fee_rate: u64,
+    pub lp_fee_share: u64,
+    pub max_hands: u32,
+    pub max_nfts: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ContractStatusResponse {
+    pub status: ContractStatus,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FeeLedgerResponse {
+    pub denom: String,
+    pub accrued_fee: Uint128,
+    pub reserve: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SharesResponse {
+    pub denom: String,
+    pub shares: Uint128,
+    pub total_shares: Uint128,
+    /// the coins redeemable for the shares
+    pub amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BetLimitsResponse {
+    pub denom: String,
+    pub min_bet: Uint128,
+    pub max_bet: Option<Uint128>,
+    pub max_payout_rate: u64,
+    /// the max payout of a win for the current reserve
+    pub max_payout: Uint128,
 }
@@ -15,6 +15,15 @@ pub enum HandleMsg {
         /// optional message length padding
         padding: Option<String>,
     },
+    RegisterReceiveNft {
+        /// receving contract's code hash
+        code_hash: String,
+        /// optionally true if the contract also implements BatchReceiveNft.  Defaults
+        /// to false if not specified
+        also_implements_batch_receive_nft: Option<bool>,
+        /// optional message length padding
+        padding: Option<String>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
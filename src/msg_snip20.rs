@@ -0,0 +1,22 @@
+use cosmwasm_std::{HumanAddr, Uint128};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HandleMsg {
+    Transfer {
+        /// recipient of the transfer
+        recipient: HumanAddr,
+        /// amount of the tokens to transfer
+        amount: Uint128,
+        /// optional message length padding
+        padding: Option<String>,
+    },
+    RegisterReceive {
+        /// receving contract's code hash
+        code_hash: String,
+        /// optional message length padding
+        padding: Option<String>,
+    },
+}
@@ -0,0 +1,198 @@
+use bech32::ToBase32;
+use cosmwasm_std::{to_binary, Binary, HumanAddr, StdError, StdResult, Uint128};
+use k256::ecdsa::signature::{Signature as _, Verifier};
+use k256::ecdsa::{Signature, VerifyingKey};
+use ripemd160::{Digest, Ripemd160};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::sha_256;
+
+pub const BECH32_PREFIX: &str = "secret";
+pub const PERMIT_MSG_TYPE: &str = "query_permit";
+
+/// the query permit of SNIP-24, signed offline by the wallet
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Permit {
+    pub params: PermitParams,
+    pub signature: PermitSignature,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PermitParams {
+    /// the contracts which the permit can be used with
+    pub allowed_tokens: Vec<HumanAddr>,
+    /// the name which the signer revokes the permit by
+    pub permit_name: String,
+    pub chain_id: String,
+    pub permissions: Vec<Permission>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PermitSignature {
+    pub pub_key: PubKey,
+    /// the compact secp256k1 signature over the sign doc
+    pub signature: Binary,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PubKey {
+    /// "tendermint/PubKeySecp256k1"
+    pub r#type: String,
+    /// the compressed secp256k1 public key
+    pub value: Binary,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// all the private queries
+    Owner,
+    /// the offers made by or made to the signer
+    Offers,
+    /// the token bets of the signer
+    TokenBets,
+    /// the shares of the reserves owned by the signer
+    Shares,
+}
+
+// the amino json sign doc of cosmos sdk, whose fields are serialized in the sorted order
+#[derive(Serialize)]
+struct SignDoc<'a> {
+    account_number: Uint128,
+    chain_id: &'a str,
+    fee: Fee,
+    memo: String,
+    msgs: Vec<PermitMsg<'a>>,
+    sequence: Uint128,
+}
+
+#[derive(Serialize)]
+struct Fee {
+    amount: Vec<FeeAmount>,
+    gas: Uint128,
+}
+
+#[derive(Serialize)]
+struct FeeAmount {
+    amount: Uint128,
+    denom: String,
+}
+
+#[derive(Serialize)]
+struct PermitMsg<'a> {
+    r#type: &'a str,
+    value: PermitMsgValue<'a>,
+}
+
+#[derive(Serialize)]
+struct PermitMsgValue<'a> {
+    allowed_tokens: &'a [HumanAddr],
+    permissions: &'a [Permission],
+    permit_name: &'a str,
+}
+
+impl PermitParams {
+    /// the bytes which the wallet signs, a transaction of zero fee which is never broadcast
+    pub fn sign_doc(&self) -> StdResult<Binary> {
+        to_binary(&SignDoc {
+            account_number: Uint128(0),
+            chain_id: &self.chain_id,
+            fee: Fee {
+                amount: vec![FeeAmount {
+                    amount: Uint128(0),
+                    denom: "uscrt".to_string(),
+                }],
+                gas: Uint128(1),
+            },
+            memo: String::new(),
+            msgs: vec![PermitMsg {
+                r#type: PERMIT_MSG_TYPE,
+                value: PermitMsgValue {
+                    allowed_tokens: &self.allowed_tokens,
+                    permissions: &self.permissions,
+                    permit_name: &self.permit_name,
+                },
+            }],
+            sequence: Uint128(0),
+        })
+    }
+}
+
+impl Permit {
+    /// returns the signer if the permit grants the permission on the contract.
+    /// the revocation is checked against the storage by the caller
+    pub fn check_permit(
+        &self,
+        contract_address: &HumanAddr,
+        permission: Permission,
+    ) -> StdResult<HumanAddr> {
+        let params = &self.params;
+        if !params.allowed_tokens.contains(contract_address) {
+            return Err(StdError::generic_err(format!(
+                "permit({}) is not for this contract({})",
+                params.permit_name, contract_address
+            )));
+        }
+        if !params.permissions.contains(&Permission::Owner)
+            && !params.permissions.contains(&permission)
+        {
+            return Err(StdError::generic_err(format!(
+                "permit({}) doesn't grant the permission({:?})",
+                params.permit_name, permission
+            )));
+        }
+
+        let pub_key = self.signature.pub_key.value.as_slice();
+        let verified = match (
+            VerifyingKey::from_sec1_bytes(pub_key),
+            Signature::from_bytes(self.signature.signature.as_slice()),
+        ) {
+            (Ok(key), Ok(signature)) => key
+                .verify(params.sign_doc()?.as_slice(), &signature)
+                .is_ok(),
+            _ => false,
+        };
+        if !verified {
+            return Err(StdError::generic_err(format!(
+                "failed to verify the signature of permit({})",
+                params.permit_name
+            )));
+        }
+        pub_key_to_address(pub_key)
+    }
+}
+
+/// the account address of cosmos sdk, the bech32 of ripemd160(sha256(public key))
+pub fn pub_key_to_address(pub_key: &[u8]) -> StdResult<HumanAddr> {
+    let hash = Ripemd160::digest(&sha_256(pub_key));
+    match bech32::encode(BECH32_PREFIX, hash.to_base32()) {
+        Ok(address) => Ok(HumanAddr(address)),
+        Err(err) => Err(StdError::generic_err(format!(
+            "invalid public key: {:?}",
+            err
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sign_doc() {
+        let params = PermitParams {
+            allowed_tokens: vec!["secret1contract".into()],
+            permit_name: "janken".to_string(),
+            chain_id: "secret-4".to_string(),
+            permissions: vec![Permission::Offers, Permission::TokenBets],
+        };
+        let expected = concat!(
+            r#"{"account_number":"0","chain_id":"secret-4","#,
+            r#""fee":{"amount":[{"amount":"0","denom":"uscrt"}],"gas":"1"},"memo":"","#,
+            r#""msgs":[{"type":"query_permit","value":{"allowed_tokens":["secret1contract"],"#,
+            r#""permissions":["offers","token_bets"],"permit_name":"janken"}}],"sequence":"0"}"#,
+        );
+        assert_eq!(expected.as_bytes(), params.sign_doc().unwrap().as_slice());
+    }
+}
@@ -1,4 +1,7 @@
-use cosmwasm_std::{CanonicalAddr, HumanAddr, ReadonlyStorage, Storage};
+use cosmwasm_std::{
+    Binary, BlockInfo, CanonicalAddr, Coin, HumanAddr, ReadonlyStorage, StdError, StdResult,
+    Storage, Uint128,
+};
 use cosmwasm_storage::{
     bucket, bucket_read, singleton, singleton_read, Bucket, PrefixedStorage, ReadonlyBucket,
     ReadonlyPrefixedStorage, ReadonlySingleton, Singleton,
@@ -6,20 +9,63 @@ use cosmwasm_storage::{
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::contract::{DEFAULT_MAX_PAYOUT_RATE, INVERSE_BASIS_POINT};
 use crate::hand::{Hand, Hands};
+use crate::utils::sha_256;
 use crate::viewing_key::ViewingKey;
 
 pub const CONFIG_KEY: &[u8] = b"config";
+pub const CONTRACT_VERSION_KEY: &[u8] = b"version";
+pub const OFFER_COUNT_KEY: &[u8] = b"offercount";
+pub const TOKEN_BET_COUNT_KEY: &[u8] = b"tokenbetcount";
+pub const EPOCH_KEY: &[u8] = b"epoch";
+pub const SERVER_SEED_KEY: &[u8] = b"serverseed";
+pub const CONTRACT_ADDRESS_KEY: &[u8] = b"contractaddress";
 pub const PREFIX_OFFERS: &[u8] = b"offers";
+pub const PREFIX_OFFER_KEYS: &[u8] = b"offerkeys";
+pub const PREFIX_OFFEROR_OFFERS: &[u8] = b"offerors";
+pub const PREFIX_OFFEREE_OFFERS: &[u8] = b"offerees";
 pub const PREFIX_TOKEN_BETS: &[u8] = b"tokenbets";
+pub const PREFIX_TOKEN_BET_KEYS: &[u8] = b"tokenbetkeys";
+pub const PREFIX_BETTOR_BETS: &[u8] = b"bettorbets";
+pub const PREFIX_PENDING_BETS: &[u8] = b"pendingbets";
+pub const PREFIX_EPOCHS: &[u8] = b"epochs";
 pub const PREFIX_VIEWING_KEY: &[u8] = b"viewingkey";
+pub const PREFIX_REVOKED_PERMITS: &[u8] = b"revokedpermits";
+pub const PREFIX_NFT_CONTRACTS: &[u8] = b"nftcontracts";
+pub const PREFIX_TOKEN_CONTRACTS: &[u8] = b"tokencontracts";
+pub const PREFIX_ACCRUED_FEES: &[u8] = b"accruedfees";
+pub const PREFIX_RESERVES: &[u8] = b"reserves";
+pub const PREFIX_HELD_RESERVES: &[u8] = b"heldreserves";
+pub const PREFIX_SHARES: &[u8] = b"shares";
+pub const PREFIX_TOTAL_SHARES: &[u8] = b"totalshares";
+pub const PREFIX_BET_LIMITS: &[u8] = b"betlimits";
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct State {
     pub prng_seed: Vec<u8>,
     pub entropy: Vec<u8>,
+    pub admin: HumanAddr,
+    /// the address proposed as the next admin, which should accept it
+    pub pending_admin: Option<HumanAddr>,
     pub fee_recipient: HumanAddr,
     pub fee_rate: u64,
+    /// the part of the token bet fee paid to the liquidity providers, in basis points
+    pub lp_fee_share: u64,
+    pub max_hands: u32,
+    pub max_nfts: u32,
+    pub contract_status: ContractStatus,
+}
+
+/// the level to stop the contract in emergency
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ContractStatus {
+    Normal,
+    /// no offer can be made or accepted and no token can be bet
+    StopNewGames,
+    /// only the offers can be cancelled or declined to refund, and the admin can operate
+    StopAll,
 }
 
 pub fn config<S: Storage>(storage: &mut S) -> Singleton<S, State> {
@@ -30,11 +76,69 @@ pub fn config_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, State> {
     singleton_read(storage, CONFIG_KEY)
 }
 
+/// the version of the stored records, missing in v0.1
+pub fn contract_version<S: Storage>(storage: &mut S) -> Singleton<S, u32> {
+    singleton(storage, CONTRACT_VERSION_KEY)
+}
+
+pub fn contract_version_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, u32> {
+    singleton_read(storage, CONTRACT_VERSION_KEY)
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub enum OfferStatus {
     Offered,
     Accepted,
     Declined,
+    Expired,
+    Cancelled,
+    Countered,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+/// at the given point in time and after, Expiration will be considered expired
+pub enum Expiration {
+    /// expires at this block height
+    AtHeight(u64),
+    /// expires at the time in seconds since 01/01/1970
+    AtTime(u64),
+    /// never expires
+    Never,
+}
+
+impl Expiration {
+    pub fn is_expired(&self, block: &BlockInfo) -> bool {
+        match self {
+            Expiration::AtHeight(height) => block.height >= *height,
+            Expiration::AtTime(time) => block.time >= *time,
+            Expiration::Never => false,
+        }
+    }
+}
+
+impl Default for Expiration {
+    fn default() -> Self {
+        Expiration::Never
+    }
+}
+
+/// the nft staked in the offer
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Nft {
+    pub contract: HumanAddr,
+    pub code_hash: String,
+    pub token_id: String,
+}
+
+/// the collection from which the open offer accepts one token
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NftCollection {
+    pub contract: HumanAddr,
+    pub code_hash: String,
+    /// the acceptable token ids, any token is acceptable if empty
+    #[serde(default)]
+    pub whitelist: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -43,60 +147,102 @@ pub struct Offer {
     pub status: OfferStatus,
     pub offeror: HumanAddr,
     pub offeree: HumanAddr,
-    pub offeror_nft_contract: HumanAddr,
-    pub offeror_nft: String,
-    pub offeror_code_hash: String,
-    pub offeree_nft_contract: HumanAddr,
-    pub offeree_nft: String,
-    pub offeree_code_hash: String,
+    pub offeror_nfts: Vec<Nft>,
+    pub offeree_nfts: Vec<Nft>,
+    /// the offer which anyone holding the token of the collection can accept, the offeree and
+    /// the offeree nfts are empty until accepted
+    pub open: bool,
+    pub offeree_collection: Option<NftCollection>,
     pub offeror_hands: Hands,
     pub offeree_hands: Hands,
     pub offeror_draw_point: i8,
+    pub expires: Expiration,
+    pub escrow: bool,
+    /// the coins which the offeror sent with the offer
+    pub offeror_funds: Vec<Coin>,
+    /// the coins which the offeree should send on accept
+    pub offeree_funds: Vec<Coin>,
+    /// the offer which this offer counters
+    pub counter_of: Option<u64>,
+    /// the offer which counters this offer
+    pub countered_by: Option<u64>,
     pub winner: String,
+    /// the participants who published their side of the settled match
+    pub offeror_published: bool,
+    pub offeree_published: bool,
 }
 
 impl Offer {
+    /// the id is assigned when the offer is saved
     pub fn new(
-        id: u64,
         offeror: HumanAddr,
         offeree: HumanAddr,
-        offeror_nft_contract: HumanAddr,
-        offeror_nft: String,
-        offeror_code_hash: String,
-        offeree_nft_contract: HumanAddr,
-        offeree_nft: String,
-        offeree_code_hash: String,
-        hands: Vec<u8>,
+        offeror_nfts: Vec<Nft>,
+        offeree_nfts: Vec<Nft>,
+        offeree_collection: Option<NftCollection>,
+        offeror_funds: Vec<Coin>,
+        offeree_funds: Vec<Coin>,
+        hands: Hands,
         draw_point: i8,
+        expires: Expiration,
+        escrow: bool,
     ) -> Offer {
         Offer {
-            id,
+            id: 0,
             status: OfferStatus::Offered,
+            open: offeree.as_str().is_empty(),
             offeror,
             offeree,
-            offeror_nft_contract,
-            offeror_nft,
-            offeror_code_hash,
-            offeree_nft_contract,
-            offeree_nft,
-            offeree_code_hash,
-            offeror_hands: hands.into(),
+            offeror_nfts,
+            offeree_nfts,
+            offeree_collection,
+            offeror_hands: hands,
             offeree_hands: Vec::<Hand>::new().into(),
             offeror_draw_point: draw_point,
+            expires,
+            escrow,
+            offeror_funds,
+            offeree_funds,
+            counter_of: None,
+            countered_by: None,
             winner: "".to_string(),
+            offeror_published: false,
+            offeree_published: false,
         }
     }
 
-    pub fn accept_offer(&mut self, offeree: HumanAddr, hands: Vec<u8>) {
+    pub fn accept_offer(&mut self, offeree: HumanAddr, hands: Hands) {
         self.status = OfferStatus::Accepted;
         self.offeree = offeree;
-        self.offeree_hands = hands.into();
+        self.offeree_hands = hands;
     }
 
     pub fn decline_offer(&mut self, offeree: HumanAddr) {
         self.status = OfferStatus::Declined;
         self.offeree = offeree;
     }
+
+    pub fn counter_offer(&mut self, counter_id: u64) {
+        self.status = OfferStatus::Countered;
+        self.countered_by = Some(counter_id);
+    }
+
+    pub fn cancel_offer(&mut self) {
+        self.status = OfferStatus::Cancelled;
+    }
+
+    pub fn expire_offer(&mut self) {
+        self.status = OfferStatus::Expired;
+    }
+
+    pub fn publish(&mut self, player: &HumanAddr) {
+        if &self.offeror == player {
+            self.offeror_published = true;
+        }
+        if &self.offeree == player {
+            self.offeree_published = true;
+        }
+    }
 }
 
 pub fn offers<S: Storage>(storage: &mut S) -> Bucket<S, Offer> {
@@ -107,13 +253,86 @@ pub fn offers_read<S: ReadonlyStorage>(storage: &S) -> ReadonlyBucket<S, Offer>
     bucket_read(PREFIX_OFFERS, storage)
 }
 
+/// offer ids indexed by the offeror
+pub fn offeror_offers<'a, S: Storage>(
+    storage: &'a mut S,
+    owner: &CanonicalAddr,
+) -> Bucket<'a, S, u64> {
+    Bucket::multilevel(&[PREFIX_OFFEROR_OFFERS, owner.as_slice()], storage)
+}
+
+pub fn offeror_offers_read<'a, S: ReadonlyStorage>(
+    storage: &'a S,
+    owner: &CanonicalAddr,
+) -> ReadonlyBucket<'a, S, u64> {
+    ReadonlyBucket::multilevel(&[PREFIX_OFFEROR_OFFERS, owner.as_slice()], storage)
+}
+
+/// offer ids indexed by the offeree
+pub fn offeree_offers<'a, S: Storage>(
+    storage: &'a mut S,
+    owner: &CanonicalAddr,
+) -> Bucket<'a, S, u64> {
+    Bucket::multilevel(&[PREFIX_OFFEREE_OFFERS, owner.as_slice()], storage)
+}
+
+pub fn offeree_offers_read<'a, S: ReadonlyStorage>(
+    storage: &'a S,
+    owner: &CanonicalAddr,
+) -> ReadonlyBucket<'a, S, u64> {
+    ReadonlyBucket::multilevel(&[PREFIX_OFFEREE_OFFERS, owner.as_slice()], storage)
+}
+
+pub fn next_offer_id<S: Storage>(storage: &mut S) -> StdResult<u64> {
+    next_id(storage, OFFER_COUNT_KEY)
+}
+
+/// offer ids by the idempotency keys which the offeror specified
+pub fn offer_keys<'a, S: Storage>(storage: &'a mut S, owner: &CanonicalAddr) -> Bucket<'a, S, u64> {
+    Bucket::multilevel(&[PREFIX_OFFER_KEYS, owner.as_slice()], storage)
+}
+
+pub fn offer_keys_read<'a, S: ReadonlyStorage>(
+    storage: &'a S,
+    owner: &CanonicalAddr,
+) -> ReadonlyBucket<'a, S, u64> {
+    ReadonlyBucket::multilevel(&[PREFIX_OFFER_KEYS, owner.as_slice()], storage)
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct TokenBet {
     pub id: u64,
+    pub bettor: CanonicalAddr,
     pub denom: String,
-    pub amount: u64,
+    pub amount: Uint128,
     pub hand: Hand,
     pub result: String,
+    /// the house hand is recomputed from the entropy and the revealed seed of the epoch
+    pub epoch: u64,
+    pub entropy: Binary,
+}
+
+/// the committed token bet to be settled in a later block
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingBet {
+    pub id: u64,
+    pub bettor: CanonicalAddr,
+    pub denom: String,
+    pub amount: Uint128,
+    pub hand: Hand,
+    /// the block height of the bet
+    pub height: u64,
+    /// the house hand is drawn from the seed of the epoch and the entropy, both fixed at the bet
+    pub epoch: u64,
+    pub entropy: Binary,
+}
+
+pub fn pending_bets<S: Storage>(storage: &mut S) -> Bucket<S, PendingBet> {
+    bucket(PREFIX_PENDING_BETS, storage)
+}
+
+pub fn pending_bets_read<S: ReadonlyStorage>(storage: &S) -> ReadonlyBucket<S, PendingBet> {
+    bucket_read(PREFIX_PENDING_BETS, storage)
 }
 
 pub fn token_bets<S: Storage>(storage: &mut S) -> Bucket<S, TokenBet> {
@@ -124,6 +343,76 @@ pub fn token_bets_read<S: ReadonlyStorage>(storage: &S) -> ReadonlyBucket<S, Tok
     bucket_read(PREFIX_TOKEN_BETS, storage)
 }
 
+/// token bet ids indexed by the bettor
+pub fn bettor_bets<'a, S: Storage>(
+    storage: &'a mut S,
+    owner: &CanonicalAddr,
+) -> Bucket<'a, S, u64> {
+    Bucket::multilevel(&[PREFIX_BETTOR_BETS, owner.as_slice()], storage)
+}
+
+pub fn bettor_bets_read<'a, S: ReadonlyStorage>(
+    storage: &'a S,
+    owner: &CanonicalAddr,
+) -> ReadonlyBucket<'a, S, u64> {
+    ReadonlyBucket::multilevel(&[PREFIX_BETTOR_BETS, owner.as_slice()], storage)
+}
+
+pub fn next_token_bet_id<S: Storage>(storage: &mut S) -> StdResult<u64> {
+    next_id(storage, TOKEN_BET_COUNT_KEY)
+}
+
+/// token bet ids by the idempotency keys which the bettor specified
+pub fn token_bet_keys<'a, S: Storage>(
+    storage: &'a mut S,
+    owner: &CanonicalAddr,
+) -> Bucket<'a, S, u64> {
+    Bucket::multilevel(&[PREFIX_TOKEN_BET_KEYS, owner.as_slice()], storage)
+}
+
+pub fn token_bet_keys_read<'a, S: ReadonlyStorage>(
+    storage: &'a S,
+    owner: &CanonicalAddr,
+) -> ReadonlyBucket<'a, S, u64> {
+    ReadonlyBucket::multilevel(&[PREFIX_TOKEN_BET_KEYS, owner.as_slice()], storage)
+}
+
+/// the last assigned offer id, restored when migrating the offers with the client given ids
+pub fn save_offer_count<S: Storage>(storage: &mut S, count: u64) -> StdResult<()> {
+    singleton(storage, OFFER_COUNT_KEY).save(&count)
+}
+
+pub fn save_token_bet_count<S: Storage>(storage: &mut S, count: u64) -> StdResult<()> {
+    singleton(storage, TOKEN_BET_COUNT_KEY).save(&count)
+}
+
+// ids start from 1 and increase monotonically
+fn next_id<S: Storage>(storage: &mut S, key: &[u8]) -> StdResult<u64> {
+    let mut count: Singleton<S, u64> = singleton(storage, key);
+    let id = count.may_load()?.unwrap_or_default() + 1;
+    count.save(&id)?;
+    Ok(id)
+}
+
+/// code hashes of the nft contracts which Janken is registered with as a receiver
+pub fn nft_contracts<S: Storage>(storage: &mut S) -> Bucket<S, String> {
+    bucket(PREFIX_NFT_CONTRACTS, storage)
+}
+
+pub fn nft_contracts_read<S: ReadonlyStorage>(storage: &S) -> ReadonlyBucket<S, String> {
+    bucket_read(PREFIX_NFT_CONTRACTS, storage)
+}
+
+/// the code hashes of the whitelisted snip20 contracts, keyed by the contract address which is
+/// used as the denom of the token
+pub fn token_contracts<S: Storage>(storage: &mut S) -> Bucket<S, String> {
+    bucket(PREFIX_TOKEN_CONTRACTS, storage)
+}
+
+pub fn token_contracts_read<S: ReadonlyStorage>(storage: &S) -> ReadonlyBucket<S, String> {
+    bucket_read(PREFIX_TOKEN_CONTRACTS, storage)
+}
+
 pub fn write_viewing_key<S: Storage>(store: &mut S, owner: &CanonicalAddr, key: &ViewingKey) {
     let mut user_key_store = PrefixedStorage::new(PREFIX_VIEWING_KEY, store);
     user_key_store.set(owner.as_slice(), &key.to_hashed());
@@ -133,3 +422,180 @@ pub fn read_viewing_key<S: Storage>(store: &S, owner: &CanonicalAddr) -> Option<
     let user_key_store = ReadonlyPrefixedStorage::new(PREFIX_VIEWING_KEY, store);
     user_key_store.get(owner.as_slice())
 }
+
+pub fn remove_viewing_key<S: Storage>(store: &mut S, owner: &CanonicalAddr) {
+    let mut user_key_store = PrefixedStorage::new(PREFIX_VIEWING_KEY, store);
+    user_key_store.remove(owner.as_slice());
+}
+
+/// the names of the permits which the signer revoked
+pub fn revoked_permits<'a, S: Storage>(
+    storage: &'a mut S,
+    owner: &CanonicalAddr,
+) -> Bucket<'a, S, bool> {
+    Bucket::multilevel(&[PREFIX_REVOKED_PERMITS, owner.as_slice()], storage)
+}
+
+pub fn revoked_permits_read<'a, S: ReadonlyStorage>(
+    storage: &'a S,
+    owner: &CanonicalAddr,
+) -> ReadonlyBucket<'a, S, bool> {
+    ReadonlyBucket::multilevel(&[PREFIX_REVOKED_PERMITS, owner.as_slice()], storage)
+}
+
+/// the address of this contract, which the permits should name as queries don't know it
+pub fn contract_address<S: Storage>(storage: &mut S) -> Singleton<S, HumanAddr> {
+    singleton(storage, CONTRACT_ADDRESS_KEY)
+}
+
+pub fn contract_address_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, HumanAddr> {
+    singleton_read(storage, CONTRACT_ADDRESS_KEY)
+}
+
+/// the fees accrued by denom, which only the fee recipient can withdraw
+pub fn accrued_fees<S: Storage>(storage: &mut S) -> Bucket<S, Uint128> {
+    bucket(PREFIX_ACCRUED_FEES, storage)
+}
+
+pub fn accrued_fees_read<S: ReadonlyStorage>(storage: &S) -> ReadonlyBucket<S, Uint128> {
+    bucket_read(PREFIX_ACCRUED_FEES, storage)
+}
+
+/// the house bankroll by denom, which pays out the winning token bets
+pub fn reserves<S: Storage>(storage: &mut S) -> Bucket<S, Uint128> {
+    bucket(PREFIX_RESERVES, storage)
+}
+
+pub fn reserves_read<S: ReadonlyStorage>(storage: &S) -> ReadonlyBucket<S, Uint128> {
+    bucket_read(PREFIX_RESERVES, storage)
+}
+
+/// the part of the reserve held for the pending bets by denom, still owned by the providers
+pub fn held_reserves<S: Storage>(storage: &mut S) -> Bucket<S, Uint128> {
+    bucket(PREFIX_HELD_RESERVES, storage)
+}
+
+pub fn held_reserves_read<S: ReadonlyStorage>(storage: &S) -> ReadonlyBucket<S, Uint128> {
+    bucket_read(PREFIX_HELD_RESERVES, storage)
+}
+
+/// the reserve shares of the liquidity providers by denom
+pub fn shares<'a, S: Storage>(storage: &'a mut S, denom: &str) -> Bucket<'a, S, Uint128> {
+    Bucket::multilevel(&[PREFIX_SHARES, denom.as_bytes()], storage)
+}
+
+pub fn shares_read<'a, S: ReadonlyStorage>(
+    storage: &'a S,
+    denom: &str,
+) -> ReadonlyBucket<'a, S, Uint128> {
+    ReadonlyBucket::multilevel(&[PREFIX_SHARES, denom.as_bytes()], storage)
+}
+
+pub fn total_shares<S: Storage>(storage: &mut S) -> Bucket<S, Uint128> {
+    bucket(PREFIX_TOTAL_SHARES, storage)
+}
+
+pub fn total_shares_read<S: ReadonlyStorage>(storage: &S) -> ReadonlyBucket<S, Uint128> {
+    bucket_read(PREFIX_TOTAL_SHARES, storage)
+}
+
+/// the limits of a token bet, set by denom
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BetLimits {
+    pub min_bet: Uint128,
+    /// no upper limit if omitted
+    pub max_bet: Option<Uint128>,
+    /// the max payout of a win as a fraction of the reserve, in basis points
+    pub max_payout_rate: u64,
+}
+
+impl BetLimits {
+    pub fn max_payout(&self, reserve: u128) -> u128 {
+        reserve * self.max_payout_rate as u128 / INVERSE_BASIS_POINT as u128
+    }
+}
+
+impl Default for BetLimits {
+    fn default() -> Self {
+        BetLimits {
+            min_bet: Uint128(1),
+            max_bet: None,
+            max_payout_rate: DEFAULT_MAX_PAYOUT_RATE,
+        }
+    }
+}
+
+pub fn bet_limits<S: Storage>(storage: &mut S) -> Bucket<S, BetLimits> {
+    bucket(PREFIX_BET_LIMITS, storage)
+}
+
+pub fn bet_limits_read<S: ReadonlyStorage>(storage: &S) -> ReadonlyBucket<S, BetLimits> {
+    bucket_read(PREFIX_BET_LIMITS, storage)
+}
+
+/// the house seed of an epoch, which is committed by the hash and revealed when rotated
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Epoch {
+    pub id: u64,
+    pub commitment: Binary,
+    pub seed: Option<Binary>,
+}
+
+pub fn epochs<S: Storage>(storage: &mut S) -> Bucket<S, Epoch> {
+    bucket(PREFIX_EPOCHS, storage)
+}
+
+pub fn epochs_read<S: ReadonlyStorage>(storage: &S) -> ReadonlyBucket<S, Epoch> {
+    bucket_read(PREFIX_EPOCHS, storage)
+}
+
+pub fn current_epoch<S: Storage>(storage: &mut S) -> Singleton<S, u64> {
+    singleton(storage, EPOCH_KEY)
+}
+
+pub fn current_epoch_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, u64> {
+    singleton_read(storage, EPOCH_KEY)
+}
+
+/// the seed of the current epoch, which is never exposed until rotated
+pub fn server_seed<S: Storage>(storage: &mut S) -> Singleton<S, Vec<u8>> {
+    singleton(storage, SERVER_SEED_KEY)
+}
+
+pub fn server_seed_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, Vec<u8>> {
+    singleton_read(storage, SERVER_SEED_KEY)
+}
+
+/// the seed of the epoch, which is secret until the epoch is rotated
+pub fn epoch_seed<S: Storage>(storage: &S, id: u64) -> StdResult<Vec<u8>> {
+    if current_epoch_read(storage).load()? == id {
+        return server_seed_read(storage).load();
+    }
+    match epochs_read(storage).load(&id.to_be_bytes())?.seed {
+        Some(seed) => Ok(seed.0),
+        None => Err(StdError::generic_err(format!(
+            "seed of epoch({}) is not found",
+            id
+        ))),
+    }
+}
+
+/// reveals the seed of the current epoch, then starts the next epoch committing to the seed
+pub fn rotate_epoch<S: Storage>(storage: &mut S, seed: &[u8]) -> StdResult<Epoch> {
+    let current = current_epoch_read(storage).may_load()?;
+    if let Some(id) = current {
+        let mut epoch = epochs_read(storage).load(&id.to_be_bytes())?;
+        epoch.seed = Some(Binary(server_seed_read(storage).load()?));
+        epochs(storage).save(&id.to_be_bytes(), &epoch)?;
+    }
+
+    let epoch = Epoch {
+        id: current.unwrap_or(0) + 1,
+        commitment: Binary(sha_256(seed).to_vec()),
+        seed: None,
+    };
+    epochs(storage).save(&epoch.id.to_be_bytes(), &epoch)?;
+    current_epoch(storage).save(&epoch.id)?;
+    server_seed(storage).save(&seed.to_vec())?;
+    Ok(epoch)
+}
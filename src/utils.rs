@@ -8,6 +8,7 @@ use crate::contract::INVERSE_BASIS_POINT;
 use crate::viewing_key::VIEWING_KEY_SIZE;
 
 pub const SHA256_HASH_SIZE: usize = 32;
+const HOUSE_SEED_TAG: &[u8] = b"janken_house_seed";
 
 pub fn ct_slice_compare(s1: &[u8], s2: &[u8]) -> bool {
     bool::from(s1.ct_eq(s2))
@@ -25,6 +26,15 @@ pub fn create_hashed_password(s1: &str) -> [u8; VIEWING_KEY_SIZE] {
 //         .unwrap_or_else(|v: Vec<T>| panic!("Expected a Vec of length {} but it was {}", N, v.len()))
 // }
 
+/// the first key of range iteration which excludes `start_after`
+pub fn range_start(start_after: Option<u64>) -> Option<Vec<u8>> {
+    start_after.map(|id| {
+        let mut key = id.to_be_bytes().to_vec();
+        key.push(0);
+        key
+    })
+}
+
 pub fn sha_256(data: &[u8]) -> [u8; SHA256_HASH_SIZE] {
     let mut hasher = Sha256::new();
     hasher.update(data);
@@ -35,8 +45,16 @@ pub fn sha_256(data: &[u8]) -> [u8; SHA256_HASH_SIZE] {
     result
 }
 
-pub fn calculate_fee(amount: u64, fee_rate: u64) -> u64 {
-    amount * fee_rate / INVERSE_BASIS_POINT
+/// the seed of the first epoch. it's hashed from the prng seed under its own tag, so revealing it
+/// tells nothing about the prng seed which the viewing keys are derived from
+pub fn house_seed(prng_seed: &[u8]) -> [u8; SHA256_HASH_SIZE] {
+    sha_256(&[HOUSE_SEED_TAG, prng_seed].concat())
+}
+
+// divided before multiplied not to overflow, the fee rate is never more than INVERSE_BASIS_POINT
+pub fn calculate_fee(amount: u128, fee_rate: u64) -> u128 {
+    let (fee_rate, basis) = (fee_rate as u128, INVERSE_BASIS_POINT as u128);
+    amount / basis * fee_rate + amount % basis * fee_rate / basis
 }
 
 pub struct Prng {
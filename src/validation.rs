@@ -1,19 +1,75 @@
 use cosmwasm_std::{
-    to_binary, Api, Coin, Extern, HumanAddr, Querier, QueryRequest, StdError, Storage, WasmQuery,
+    to_binary, Api, BlockInfo, CanonicalAddr, Coin, Extern, HumanAddr, Querier, QueryRequest,
+    StdError, Storage, WasmQuery,
 };
+use std::convert::TryFrom;
 
+use crate::contract::{INVERSE_BASIS_POINT, MAX_HANDS_LIMIT, MAX_NFTS_LIMIT};
+use crate::hand::Hands;
+use crate::msg::HandleMsg;
 use crate::msg_cw721::{QueryAnswer, QueryMsg as Cw721QueryMsg};
-use crate::state::{config_read, State};
-use crate::state::{offers, offers_read, token_bets_read, Offer};
+use crate::permit::{Permission, Permit};
+use crate::state::{
+    accrued_fees_read, bet_limits_read, contract_address_read, nft_contracts_read, offer_keys_read,
+    offers, offers_read, pending_bets_read, read_viewing_key, reserves_read, revoked_permits_read,
+    shares_read, token_bet_keys_read, token_contracts_read, Expiration, Nft, NftCollection, Offer,
+    OfferStatus,
+};
+use crate::state::{config_read, BetLimits, ContractStatus, PendingBet, State};
+use crate::utils::calculate_fee;
+use crate::viewing_key::{ViewingKey, VIEWING_KEY_SIZE};
 
-pub fn validate_offer_id<S: Storage, A: Api, Q: Querier>(
+pub fn validate_offer_key<S: Storage, A: Api, Q: Querier>(
     deps: &Extern<S, A, Q>,
-    id: u64,
+    owner: &CanonicalAddr,
+    key: u64,
 ) -> Result<bool, StdError> {
-    match offers_read(&deps.storage).may_load(&id.to_be_bytes()) {
-        Ok(None) => return Ok(true),
-        _ => return Err(StdError::generic_err(format!("duplicated id({})", id))),
+    match offer_keys_read(&deps.storage, owner).may_load(&key.to_be_bytes()) {
+        Ok(None) => Ok(true),
+        Ok(Some(id)) => Err(StdError::generic_err(format!(
+            "duplicated id({}), already assigned to {}",
+            key, id
+        ))),
+        Err(err) => Err(err),
+    }
+}
+
+pub fn validate_hands(hands: &[u8], max_hands: u32) -> Result<Hands, StdError> {
+    if hands.is_empty() {
+        return Err(StdError::generic_err("hands are empty"));
+    }
+    if hands.len() > max_hands as usize {
+        return Err(StdError::generic_err(format!(
+            "too many hands({}), max: {}",
+            hands.len(),
+            max_hands
+        )));
+    }
+    Hands::try_from(hands.to_vec())
+}
+
+pub fn validate_offeree_hands(hands: &[u8], offeror_hands: &Hands) -> Result<Hands, StdError> {
+    let hands = Hands::try_from(hands.to_vec())?;
+    if hands.len() != offeror_hands.len() {
+        return Err(StdError::generic_err(format!(
+            "offeree hands length({}) is not same as offeror hands length({})",
+            hands.len(),
+            offeror_hands.len()
+        )));
+    }
+    Ok(hands)
+}
+
+// the offeror can neither always win nor never win
+pub fn validate_draw_point(draw_point: i8, hands: &Hands) -> Result<bool, StdError> {
+    let max = hands.len() as i16 - 1;
+    if (draw_point as i16).abs() > max {
+        return Err(StdError::generic_err(format!(
+            "offeror_draw_point({}) is out of range, should be between {} and {}",
+            draw_point, -max, max
+        )));
     }
+    Ok(true)
 }
 
 pub fn validate_nft<S: Storage, A: Api, Q: Querier>(
@@ -50,6 +106,80 @@ pub fn validate_nft<S: Storage, A: Api, Q: Querier>(
     Ok(true)
 }
 
+pub fn validate_nfts<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    nfts: &[Nft],
+    expected_owner: &HumanAddr,
+) -> Result<bool, StdError> {
+    for nft in nfts {
+        validate_nft(
+            deps,
+            nft.contract.clone(),
+            nft.token_id.clone(),
+            nft.code_hash.clone(),
+            expected_owner.clone(),
+        )?;
+    }
+    Ok(true)
+}
+
+// each side stakes nfts, coins or both of them
+pub fn validate_stakes(nfts: &[Nft], funds: &[Coin], max_nfts: u32) -> Result<bool, StdError> {
+    if nfts.is_empty() && funds.is_empty() {
+        return Err(StdError::generic_err("neither nfts nor funds are staked"));
+    }
+    validate_nft_bundle(nfts, max_nfts)
+}
+
+pub fn validate_nft_bundle(nfts: &[Nft], max_nfts: u32) -> Result<bool, StdError> {
+    if nfts.len() > max_nfts as usize {
+        return Err(StdError::generic_err(format!(
+            "too many nfts({}), max: {}",
+            nfts.len(),
+            max_nfts
+        )));
+    }
+    for (i, nft) in nfts.iter().enumerate() {
+        if nfts[..i]
+            .iter()
+            .any(|n| n.contract == nft.contract && n.token_id == nft.token_id)
+        {
+            return Err(StdError::generic_err(format!(
+                "duplicated nft({}) of {}",
+                nft.token_id, nft.contract
+            )));
+        }
+    }
+    Ok(true)
+}
+
+pub fn validate_nft_contract<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    contract_addr: &HumanAddr,
+) -> Result<String, StdError> {
+    let contract = deps.api.canonical_address(contract_addr)?;
+    match nft_contracts_read(&deps.storage).may_load(contract.as_slice()) {
+        Ok(Some(code_hash)) => Ok(code_hash),
+        _ => Err(StdError::generic_err(format!(
+            "unregistered nft contract({})",
+            contract_addr
+        ))),
+    }
+}
+
+pub fn validate_token_contract<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    contract_addr: &HumanAddr,
+) -> Result<String, StdError> {
+    match token_contracts_read(&deps.storage).may_load(contract_addr.as_str().as_bytes()) {
+        Ok(Some(code_hash)) => Ok(code_hash),
+        _ => Err(StdError::generic_err(format!(
+            "unregistered token contract({})",
+            contract_addr
+        ))),
+    }
+}
+
 pub fn validate_offeree<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     offeree: HumanAddr,
@@ -60,25 +190,160 @@ pub fn validate_offeree<S: Storage, A: Api, Q: Querier>(
         Err(err) => return Err(StdError::generic_err(format!("invalid id: {:?}", err))),
     };
 
-    if &offer.offeree != &offeree {
+    if !offer.open && &offer.offeree != &offeree {
         return Err(StdError::generic_err(format!(
             "msg sender is not offeree({})",
             &offer.offeree
         )));
     }
+    validate_offer_status(&offer)?;
     Ok(offer)
 }
 
-pub fn validate_token_bet_id<S: Storage, A: Api, Q: Querier>(
+/// returns the offeree, the offeree nfts and the collection of the open offer.
+/// the offeree and the offeree nfts are empty for the open offer
+pub fn validate_offeree_terms(
+    offeree: Option<HumanAddr>,
+    offeree_nfts: Option<Vec<Nft>>,
+    offeree_collection: Option<NftCollection>,
+) -> Result<(HumanAddr, Vec<Nft>, Option<NftCollection>), StdError> {
+    match (offeree, offeree_nfts, offeree_collection) {
+        (Some(offeree), Some(offeree_nfts), None) => Ok((offeree, offeree_nfts, None)),
+        (None, None, Some(collection)) => Ok((HumanAddr::default(), vec![], Some(collection))),
+        _ => Err(StdError::generic_err(
+            "specify both offeree and offeree_nfts, or only offeree_collection for open offer",
+        )),
+    }
+}
+
+/// returns the nfts which the offeree stakes
+pub fn validate_offeree_nfts(
+    offer: &Offer,
+    offeree_nft: Option<String>,
+) -> Result<Vec<Nft>, StdError> {
+    let collection = match &offer.offeree_collection {
+        Some(collection) if offer.open => collection,
+        _ => {
+            return match offeree_nft {
+                Some(nft) if !offer.offeree_nfts.iter().any(|n| n.token_id == nft) => Err(
+                    StdError::generic_err(format!("nft({}) is not the offered one", nft)),
+                ),
+                _ => Ok(offer.offeree_nfts.clone()),
+            };
+        }
+    };
+    match offeree_nft {
+        Some(nft) if collection.whitelist.is_empty() || collection.whitelist.contains(&nft) => {
+            Ok(vec![Nft {
+                contract: collection.contract.clone(),
+                code_hash: collection.code_hash.clone(),
+                token_id: nft,
+            }])
+        }
+        Some(nft) => Err(StdError::generic_err(format!(
+            "nft({}) is not in the whitelist",
+            nft
+        ))),
+        None => Err(StdError::generic_err(
+            "offeree_nft is required for open offer",
+        )),
+    }
+}
+
+pub fn validate_offeror<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    offeror: HumanAddr,
+    id: u64,
+) -> Result<Offer, StdError> {
+    let offer = match offers(&mut deps.storage).load(&id.to_be_bytes()) {
+        Ok(offer) => offer,
+        Err(err) => return Err(StdError::generic_err(format!("invalid id: {:?}", err))),
+    };
+
+    if &offer.offeror != &offeror {
+        return Err(StdError::generic_err(format!(
+            "msg sender is not offeror({})",
+            &offer.offeror
+        )));
+    }
+    validate_offer_status(&offer)?;
+    Ok(offer)
+}
+
+pub fn validate_publisher<S: Storage, A: Api, Q: Querier>(
     deps: &Extern<S, A, Q>,
+    publisher: &HumanAddr,
     id: u64,
+) -> Result<Offer, StdError> {
+    let offer = match offers_read(&deps.storage).load(&id.to_be_bytes()) {
+        Ok(offer) => offer,
+        Err(err) => return Err(StdError::generic_err(format!("invalid id: {:?}", err))),
+    };
+
+    if &offer.offeror != publisher && &offer.offeree != publisher {
+        return Err(StdError::generic_err(format!(
+            "msg sender is not a participant of offer({})",
+            id
+        )));
+    }
+    if offer.status != OfferStatus::Accepted {
+        return Err(StdError::generic_err(format!(
+            "offer({}) is not settled, status: {:?}",
+            id, offer.status
+        )));
+    }
+    Ok(offer)
+}
+
+pub fn validate_offer_status(offer: &Offer) -> Result<bool, StdError> {
+    if offer.status != OfferStatus::Offered {
+        return Err(StdError::generic_err(format!(
+            "offer({}) is already closed, status: {:?}",
+            offer.id, offer.status
+        )));
+    }
+    Ok(true)
+}
+
+pub fn validate_expiration(expires: &Expiration, block: &BlockInfo) -> Result<bool, StdError> {
+    if expires.is_expired(block) {
+        return Err(StdError::generic_err(format!(
+            "expiration is already passed({:?})",
+            expires
+        )));
+    }
+    Ok(true)
+}
+
+pub fn validate_token_bet_key<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    owner: &CanonicalAddr,
+    key: u64,
 ) -> Result<bool, StdError> {
-    match token_bets_read(&deps.storage).may_load(&id.to_be_bytes()) {
-        Ok(None) => return Ok(true),
-        _ => return Err(StdError::generic_err(format!("duplicated id({})", id))),
+    match token_bet_keys_read(&deps.storage, owner).may_load(&key.to_be_bytes()) {
+        Ok(None) => Ok(true),
+        Ok(Some(id)) => Err(StdError::generic_err(format!(
+            "duplicated id({}), already assigned to {}",
+            key, id
+        ))),
+        Err(err) => Err(err),
     }
 }
 
+pub fn validate_offeree_funds(sent: &[Coin], requested: &[Coin]) -> Result<bool, StdError> {
+    let mut sent = sent.to_vec();
+    sent.sort_by(|a, b| a.denom.cmp(&b.denom));
+    let mut requested = requested.to_vec();
+    requested.sort_by(|a, b| a.denom.cmp(&b.denom));
+    if sent != requested {
+        return Err(StdError::generic_err(format!(
+            "sent funds({:?}) are not the requested ones({:?})",
+            sent, requested
+        )));
+    }
+    Ok(true)
+}
+
 pub fn validate_sent_funds(funds: Vec<Coin>) -> Result<Coin, StdError> {
     if funds.len() != 1 {
         return Err(StdError::generic_err(format!(
@@ -97,31 +362,297 @@ pub fn validate_sent_funds(funds: Vec<Coin>) -> Result<Coin, StdError> {
     Ok(fund.clone())
 }
 
-pub fn validate_balance<S: Storage, A: Api, Q: Querier>(
+pub fn validate_reserve<S: Storage, A: Api, Q: Querier>(
     deps: &Extern<S, A, Q>,
-    address: &HumanAddr,
     denom: &str,
     amount: u128,
-) -> Result<bool, StdError> {
-    let balance = deps.querier.query_balance(address, denom)?;
-    if balance.amount.u128() < amount as u128 {
+) -> Result<u128, StdError> {
+    let reserve = reserves_read(&deps.storage)
+        .may_load(denom.as_bytes())?
+        .unwrap_or_default()
+        .u128();
+    // the reserve is never emptied while the shares remain
+    if reserve <= amount {
         return Err(StdError::generic_err(format!(
-            "insufficient balance in address({})",
-            address
+            "insufficient reserve of {}({})",
+            denom, reserve
         )));
     }
-    Ok(true)
+    Ok(reserve)
+}
+
+// the payout of a win should be within the limits as well as the bet itself
+pub fn validate_bet_amount<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    denom: &str,
+    amount: u128,
+    reserve: u128,
+    fee_rate: u64,
+) -> Result<u128, StdError> {
+    let limits = bet_limits_read(&deps.storage)
+        .may_load(denom.as_bytes())?
+        .unwrap_or_default();
+    if amount < limits.min_bet.u128() {
+        return Err(StdError::generic_err(format!(
+            "bet({}) is less than the min bet({})",
+            amount, limits.min_bet
+        )));
+    }
+    if let Some(max_bet) = limits.max_bet {
+        if amount > max_bet.u128() {
+            return Err(StdError::generic_err(format!(
+                "bet({}) is more than the max bet({})",
+                amount, max_bet
+            )));
+        }
+    }
+    let payout = amount.saturating_mul(2) - calculate_fee(amount, fee_rate);
+    let max_payout = limits.max_payout(reserve);
+    if payout > max_payout {
+        return Err(StdError::generic_err(format!(
+            "payout({}) is more than the max payout({})",
+            payout, max_payout
+        )));
+    }
+    Ok(amount)
+}
+
+pub fn validate_bet_limits(limits: BetLimits) -> Result<BetLimits, StdError> {
+    if let Some(max_bet) = limits.max_bet {
+        if limits.min_bet.u128() > max_bet.u128() {
+            return Err(StdError::generic_err(format!(
+                "min_bet({}) should not be more than max_bet({})",
+                limits.min_bet, max_bet
+            )));
+        }
+    }
+    if limits.max_payout_rate > INVERSE_BASIS_POINT {
+        return Err(StdError::generic_err(format!(
+            "max_payout_rate({}) should not be more than {}",
+            limits.max_payout_rate, INVERSE_BASIS_POINT
+        )));
+    }
+    Ok(limits)
+}
+
+pub fn validate_pending_bet<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    id: u64,
+    block: &BlockInfo,
+) -> Result<PendingBet, StdError> {
+    let bet = match pending_bets_read(&deps.storage).may_load(&id.to_be_bytes())? {
+        Some(bet) => bet,
+        None => {
+            return Err(StdError::generic_err(format!(
+                "pending bet({}) is not found",
+                id
+            )))
+        }
+    };
+    if block.height <= bet.height {
+        return Err(StdError::generic_err(format!(
+            "bet({}) can't be settled until the next block",
+            id
+        )));
+    }
+    Ok(bet)
 }
 
 pub fn validate_withdrawer<S: Storage, A: Api, Q: Querier>(
     deps: &Extern<S, A, Q>,
     address: &HumanAddr,
     denom: &str,
-    amount: u64,
-) -> Result<bool, StdError> {
+    amount: u128,
+) -> Result<u128, StdError> {
     let state: State = config_read(&deps.storage).load()?;
     if address != &state.fee_recipient {
         return Err(StdError::unauthorized());
     }
-    validate_balance(deps, address, denom, amount.into())
+    if amount == 0 {
+        return Err(StdError::generic_err(
+            "withdrawal amount should not be zero",
+        ));
+    }
+    let accrued_fee = accrued_fees_read(&deps.storage)
+        .may_load(denom.as_bytes())?
+        .unwrap_or_default()
+        .u128();
+    if accrued_fee < amount {
+        return Err(StdError::generic_err(format!(
+            "insufficient accrued fee of {}({})",
+            denom, accrued_fee
+        )));
+    }
+    Ok(accrued_fee)
+}
+
+pub fn validate_shares<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    owner: &CanonicalAddr,
+    denom: &str,
+    amount: u128,
+) -> Result<u128, StdError> {
+    let owned = shares_read(&deps.storage, denom)
+        .may_load(owner.as_slice())?
+        .unwrap_or_default()
+        .u128();
+    if amount == 0 || owned < amount {
+        return Err(StdError::generic_err(format!(
+            "insufficient shares of {}({})",
+            denom, owned
+        )));
+    }
+    Ok(owned)
+}
+
+pub fn validate_admin<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: &HumanAddr,
+) -> Result<State, StdError> {
+    let state: State = config_read(&deps.storage).load()?;
+    if address != &state.admin {
+        return Err(StdError::unauthorized());
+    }
+    Ok(state)
+}
+
+// the offers can always be cancelled or declined so that the staked nfts and funds are refunded
+pub fn validate_contract_status(status: ContractStatus, msg: &HandleMsg) -> Result<bool, StdError> {
+    match (status, msg) {
+        (ContractStatus::Normal, _) => Ok(true),
+        (_, HandleMsg::CancelOffer { .. })
+        | (_, HandleMsg::DeclineOffer { .. })
+        | (_, HandleMsg::UpdateConfig { .. })
+        | (_, HandleMsg::ChangeAdmin { .. })
+        | (_, HandleMsg::AcceptAdmin {})
+        | (_, HandleMsg::SetContractStatus { .. })
+        | (_, HandleMsg::SetBetLimits { .. })
+        | (_, HandleMsg::RegisterToken { .. })
+        | (_, HandleMsg::RegisterReceiveNft { .. })
+        | (_, HandleMsg::RotateSeed { .. })
+        | (_, HandleMsg::RevokePermit { .. })
+        | (_, HandleMsg::RevokeViewingKey { .. })
+        | (_, HandleMsg::SettleBet { .. })
+        | (_, HandleMsg::Withdraw { .. }) => Ok(true),
+        (ContractStatus::StopAll, _) => Err(StdError::generic_err("the contract is stopped")),
+        (ContractStatus::StopNewGames, HandleMsg::MakeOffer { .. })
+        | (ContractStatus::StopNewGames, HandleMsg::AcceptOffer { .. })
+        | (ContractStatus::StopNewGames, HandleMsg::CounterOffer { .. })
+        | (ContractStatus::StopNewGames, HandleMsg::ReceiveNft { .. })
+        | (ContractStatus::StopNewGames, HandleMsg::BatchReceiveNft { .. })
+        | (ContractStatus::StopNewGames, HandleMsg::Receive { .. })
+        | (ContractStatus::StopNewGames, HandleMsg::BetToken { .. }) => Err(StdError::generic_err(
+            "the contract is stopped for new games",
+        )),
+        (ContractStatus::StopNewGames, _) => Ok(true),
+    }
+}
+
+pub fn validate_fee_rate(fee_rate: u64) -> Result<u64, StdError> {
+    if fee_rate > INVERSE_BASIS_POINT {
+        return Err(StdError::generic_err(format!(
+            "fee_rate({}) should not be more than {}",
+            fee_rate, INVERSE_BASIS_POINT
+        )));
+    }
+    Ok(fee_rate)
+}
+
+pub fn validate_lp_fee_share(lp_fee_share: u64) -> Result<u64, StdError> {
+    if lp_fee_share > INVERSE_BASIS_POINT {
+        return Err(StdError::generic_err(format!(
+            "lp_fee_share({}) should not be more than {}",
+            lp_fee_share, INVERSE_BASIS_POINT
+        )));
+    }
+    Ok(lp_fee_share)
+}
+
+pub fn validate_max_hands(max_hands: u32) -> Result<u32, StdError> {
+    if max_hands == 0 || max_hands > MAX_HANDS_LIMIT {
+        return Err(StdError::generic_err(format!(
+            "max_hands({}) should be between 1 and {}",
+            max_hands, MAX_HANDS_LIMIT
+        )));
+    }
+    Ok(max_hands)
+}
+
+pub fn validate_max_nfts(max_nfts: u32) -> Result<u32, StdError> {
+    if max_nfts == 0 || max_nfts > MAX_NFTS_LIMIT {
+        return Err(StdError::generic_err(format!(
+            "max_nfts({}) should be between 1 and {}",
+            max_nfts, MAX_NFTS_LIMIT
+        )));
+    }
+    Ok(max_nfts)
+}
+
+// the key is compared even if the address has no key, so that the time doesn't tell whether it
+// has one. every failure is the same unauthorized error
+pub fn validate_viewing_key<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: &HumanAddr,
+    viewing_key: String,
+) -> Result<CanonicalAddr, StdError> {
+    let owner = match deps.api.canonical_address(address) {
+        Ok(owner) => owner,
+        Err(_) => return Err(StdError::unauthorized()),
+    };
+    let expected_key = read_viewing_key(&deps.storage, &owner);
+    let has_key = expected_key.is_some();
+    let expected_key = expected_key.unwrap_or_else(|| vec![0u8; VIEWING_KEY_SIZE]);
+    if ViewingKey(viewing_key).check_viewing_key(&expected_key) && has_key {
+        Ok(owner)
+    } else {
+        Err(StdError::unauthorized())
+    }
+}
+
+/// the credential of the private queries
+pub enum QueryAuth {
+    ViewingKey {
+        address: HumanAddr,
+        viewing_key: String,
+    },
+    Permit(Permit),
+}
+
+/// returns the address proven by the viewing key or the permit, which every private query uses.
+/// the viewing key grants all the permissions of the address
+pub fn validate_query_auth<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    auth: QueryAuth,
+    permission: Permission,
+) -> Result<HumanAddr, StdError> {
+    match auth {
+        QueryAuth::ViewingKey {
+            address,
+            viewing_key,
+        } => {
+            validate_viewing_key(&deps, &address, viewing_key)?;
+            Ok(address)
+        }
+        QueryAuth::Permit(permit) => validate_permit(&deps, &permit, permission),
+    }
+}
+
+pub fn validate_permit<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    permit: &Permit,
+    permission: Permission,
+) -> Result<HumanAddr, StdError> {
+    let contract_address = contract_address_read(&deps.storage).load()?;
+    let signer = permit.check_permit(&contract_address, permission)?;
+
+    let owner = deps.api.canonical_address(&signer)?;
+    let name = &permit.params.permit_name;
+    let revoked = revoked_permits_read(&deps.storage, &owner).may_load(name.as_bytes())?;
+    if revoked.unwrap_or_default() {
+        return Err(StdError::generic_err(format!(
+            "permit({}) is revoked by {}",
+            name, signer
+        )));
+    }
+    Ok(signer)
 }